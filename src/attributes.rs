@@ -0,0 +1,100 @@
+use crate::helper::Codec;
+use byteordered::ByteOrdered;
+use std::io::Cursor;
+
+pub const COMMENT_PREFIX: &str = "attribute: ";
+
+#[derive(Debug)]
+pub enum Layout {
+    // each entry is an offset to a null-terminated string stored after the entries (BotW)
+    Strings,
+    // each entry is a fixed number of raw bytes, shown as hex
+    Raw(u32)
+}
+
+fn header(bytes: &[u8], codec: &Codec) -> Option<(u32, u32)> {
+    let mut rdr = ByteOrdered::runtime(Cursor::new(bytes), codec.endianness);
+    Some((rdr.read_u32().ok()?, rdr.read_u32().ok()?))
+}
+
+fn offsets(bytes: &[u8], codec: &Codec, count: u32) -> Option<Vec<usize>> {
+    let mut rdr = ByteOrdered::runtime(Cursor::new(&bytes[8..]), codec.endianness);
+    (0..count).map(|_| rdr.read_u32().ok().map(|o| o as usize)).collect()
+}
+
+pub fn count(bytes: &[u8], codec: &Codec) -> Option<u32> {
+    header(bytes, codec).map(|(count, _)| count)
+}
+
+pub fn layout(bytes: &[u8], codec: &Codec) -> Option<Layout> {
+    let (count, size) = header(bytes, codec)?;
+    let table_end = 8 + count as usize * size as usize;
+    if size == 4 && bytes.len() > table_end {
+        let offsets = offsets(bytes, codec, count)?;
+        if offsets.iter().all(|&o| o >= table_end && o < bytes.len()) {
+            return Some(Layout::Strings);
+        }
+    }
+    if bytes.len() == table_end {
+        return Some(Layout::Raw(size));
+    }
+    None
+}
+
+pub fn decode(bytes: &[u8], codec: &Codec) -> Option<Vec<String>> {
+    let (count, size) = header(bytes, codec)?;
+    match layout(bytes, codec)? {
+        Layout::Strings => {
+            let unit = codec.encode("\0").len();
+            let values = offsets(bytes, codec, count)?.into_iter().map(|start| {
+                let len = bytes[start..].chunks(unit)
+                    .position(|c| c.iter().all(|&b| b == 0))
+                    .unwrap_or((bytes.len() - start) / unit);
+                codec.decode(&bytes[start..start + len * unit])
+            }).collect();
+            Some(values)
+        },
+        Layout::Raw(_) => Some((0..count as usize)
+            .map(|i| {
+                let start = 8 + i * size as usize;
+                hex::encode_upper(&bytes[start..start + size as usize])
+            })
+            .collect())
+    }
+}
+
+// Errors carry the index of the offending value
+pub fn encode(layout: &Layout, values: &[String], codec: &Codec) -> Result<Vec<u8>, (usize, String)> {
+    let mut result = Vec::new();
+    let mut writer = ByteOrdered::runtime(&mut result, codec.endianness);
+    writer.write_u32(values.len() as u32).unwrap();
+    match layout {
+        Layout::Strings => {
+            writer.write_u32(4).unwrap();
+            let mut strings = Vec::new();
+            let mut offset = 8 + values.len() * 4;
+            for value in values {
+                writer.write_u32(offset as u32).unwrap();
+                let bytes = codec.encode(&format!("{}\0", value));
+                offset += bytes.len();
+                strings.extend(bytes);
+            }
+            result.extend(strings);
+        },
+        Layout::Raw(size) => {
+            writer.write_u32(*size).unwrap();
+            for (i, value) in values.iter().enumerate() {
+                let mut bytes = if value.is_empty() {
+                    vec![0; *size as usize]
+                } else {
+                    hex::decode(value).map_err(|_| (i, format!("attribute: expected hex bytes, found \"{}\"", value)))?
+                };
+                if bytes.len() != *size as usize {
+                    return Err((i, format!("attribute: expected {} bytes, found \"{}\"", size, value)));
+                }
+                result.append(&mut bytes);
+            }
+        }
+    }
+    Ok(result)
+}
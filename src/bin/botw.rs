@@ -1,189 +1,495 @@
-use std::io::{BufReader, BufWriter};
-use msbt::{Msbt, Encoding};
-use potty::PotMessage;
-use msbt::section::txt2::Token;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
 use std::fs::File;
-use potty_msbt::{
-    param_u8, param_u16,
-    param_str, param_bytes,
-    tag_code_maps,
-    helper::{Param, Value, self}
-};
-
-tag_code_maps! {
-    (0x00, 0x00, RUBY, "Ruby"),
-    (0x00, 0x01, FONT_FACE, "Font"),
-    (0x00, 0x02, FONT_SIZE, "Size"),
-    (0x00, 0x03, COLOR, "Color"),
-    (0x00, 0x04, PAGE_BREAK, "PageBreak"),
-    (0x01, 0x00, PAUSE, "Pause"),
-    (0x01, 0x03, PAUSE_AUTO, "PauseAuto"),
-    (0x01, 0x04, CHOICE2, "Choice2"),
-    (0x01, 0x05, CHOICE3, "Choice3"),
-    (0x01, 0x06, CHOICE4, "Choice4"),
-    (0x01, 0x07, ICON, "Icon"),
-    (0x01, 0x08, CHOICE4FLAGS, "Choice4Flags"),
-    (0x01, 0x09, CHOICE4UNKNOWN, "Choice4Unknown"),
-    (0x01, 0x0A, CHOICE1, "Choice1"),
-    (0x02, 0x03, HORSE_ACTIVE, "ActiveHorse"),
-    (0x02, 0x04, HORSE_STABLE, "StableHorse"),
-    (0x03, 0x01, SOUND1, "Sound1"),
-    (0x04, 0x01, SOUND2, "Sound2"),
-    (0x04, 0x02, ANIMATION, "Animation"),
-    (0x05, 0x00, PAUSE_SHORT, "PauseShort"),
-    (0x05, 0x01, PAUSE_MID, "PauseMid"),
-    (0x05, 0x02, PAUSE_LONG, "PauseLong"),
-    (0xC9, 0x05, GENDER, "Gender"),
-    (0xC9, 0x06, SPSWITCH, "SPSwitch")
+use std::path::{Path, PathBuf};
+use std::ffi::OsStr;
+use std::panic::AssertUnwindSafe;
+use std::sync::Mutex;
+use potty::Pot;
+use potty_msbt::{Error, Locale, Profile, Rstb, Sarc, games};
+use potty_msbt::info::{MsbtSummary, PoSummary};
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+const USAGE: &str = "usage: botw <command> [options] <files or directories>...
+
+commands:
+  extract   MSBT to PO
+  build     PO to MSBT
+  verify    check that MSBT files come back byte for byte through PO
+  info      summarise MSBT and PO files
+  lint      check PO files for build errors and entries to review
+  unpack    Bootup_<region>.pack to a <region> folder of PO files
+  repack    rebuild Bootup_<region>.pack files from their PO folders,
+            writing only the packs with changed messages
+
+options:
+  --profile <name>   built-in profile botw (default) or cardboard, or a TOML or
+                     YAML profile file
+  --out <dir>        write outputs under dir, mirroring directory inputs
+  --force            overwrite existing outputs
+  --quiet            only report problems
+
+extract options:
+  --source <path>    bilingual PO with text from this MSBT file or directory as msgid
+  --template         write POT templates
+  --locale <code>    region code like EUfr or BCP 47 tag like fr-FR, otherwise taken from the path
+  --plurals          write messages with an SPSwitch tag as gettext plurals
+  --variants         write each Gender, Particle and ParticleRo string as its own entry
+  --merge            keep comments, flags and translations of the PO files being replaced
+
+unpack and repack options:
+  --locale <code>    region of the pack, otherwise taken from its file name
+  --po <dir>         read the <region> folder from dir instead of next to the pack (repack)
+  --level <0-9>      Yaz0 compression level for rebuilt archives, default 6 (repack)
+  --rstb <file>      raise the resource size table entries of changed files (repack)
+
+exit codes: 0 ok, 1 a file failed, 2 bad usage, 3 verify or lint found problems";
+
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_PROBLEMS: i32 = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    Extract,
+    Build,
+    Verify,
+    Info,
+    Lint,
+    Unpack,
+    Repack
 }
 
-pub const FONT_FACES: [(&str, &str); 2] = [
-    ("0", "hylian"), ("65535", "unset")
-];
-
-pub const COLOR_NAMES: [(&str, &str); 7] = [
-    ("0", "red"), ("1", "green"), ("2", "blue"), ("3", "gray"),
-    ("4", "white"), ("5", "orange"), ("65535", "unset")
-];
-
-fn new_params(name: &str) -> Vec<Param> {
-    let (group, tag) = tag_name_to_codes(&name).unwrap();
-    let mut params = match name {
-        tag_name::RUBY => vec![
-            param_u16!("width"),
-            param_str!("rt")
-        ],
-        tag_name::FONT_FACE => vec![
-            param_u16!("face", 0, &FONT_FACES)
-        ],
-        tag_name::FONT_SIZE => vec![
-            param_u16!("percent")
-        ],
-        tag_name::COLOR => vec![
-            param_u16!("name", 0, &COLOR_NAMES)
-        ],
-        tag_name::PAUSE | tag_name::PAUSE_AUTO => vec![
-            param_u16!("frames"),
-            param_u16!("stub")
-        ],
-        tag_name::CHOICE2 => vec![
-            param_u16!("label1"),
-            param_u16!("label2"),
-            param_u8!("select_idx"),
-            param_u8!("cancel_idx")
-        ],
-        tag_name::CHOICE3 => vec![
-            param_u16!("label1"),
-            param_u16!("label2"),
-            param_u16!("label3"),
-            param_u8!("select_idx"),
-            param_u8!("cancel_idx")
-        ],
-        tag_name::CHOICE4 => vec![
-            param_u16!("label1"),
-            param_u16!("label2"),
-            param_u16!("label3"),
-            param_u16!("label4"),
-            param_u8!("select_idx"),
-            param_u8!("cancel_idx")
-        ],
-        tag_name::ICON | tag_name::SOUND2 => vec![
-            param_u8!("id"),
-            param_bytes!("stub", vec![0xCD]),
-        ],
-        tag_name::CHOICE4FLAGS => vec![
-            param_u16!("label1"),
-            param_str!("flag1"),
-            param_u16!("label2"),
-            param_str!("flag2"),
-            param_u16!("label3"),
-            param_str!("flag3"),
-            param_u16!("label4"),
-            param_str!("flag4"),
-            param_u8!("select_idx"),
-            param_u8!("cancel_idx")
-        ],
-        tag_name::CHOICE4UNKNOWN => vec![
-            param_u16!("label1"),
-            param_str!("flag1"),
-            param_u16!("label2"),
-            param_str!("flag2"),
-            param_u16!("label3"),
-            param_str!("flag3"),
-            param_u16!("label4"),
-            param_str!("flag4"),
-            param_u16!("unk5"),
-            param_str!("name5")
-        ],
-        tag_name::CHOICE1 => vec![
-            param_u16!("label"),
-            param_bytes!("stub", vec![0x01, 0xCD])
-        ],
-        tag_name::ANIMATION => vec![
-            param_str!("name")
-        ],
-        tag_name::GENDER => vec![
-            param_str!("masculine"),
-            param_str!("feminine"),
-            param_str!("unk")
-        ],
-        tag_name::SPSWITCH => vec![
-            param_str!("singular"),
-            param_str!("plural"),
-            param_str!("plural2")
-        ],
-        _ => vec![]
-    };
+impl Command {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "extract" => Some(Self::Extract),
+            "build" => Some(Self::Build),
+            "verify" => Some(Self::Verify),
+            "info" => Some(Self::Info),
+            "lint" => Some(Self::Lint),
+            "unpack" => Some(Self::Unpack),
+            "repack" => Some(Self::Repack),
+            _ => None
+        }
+    }
 
-    if group == 0x02 {
-        params.extend(match tag {
-            0x01 | 0x02 | 0x09 | 0x0B | 0x0C | 0x0E |
-            0x0F | 0x10 | 0x11 | 0x12 | 0x13 => vec![
-                param_str!("name"),
-                param_u16!("")
-            ],
-            _ => vec![]
-        });
-    };
+    // Files picked up from directory inputs
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Extract | Self::Verify => &["msbt"],
+            Self::Build | Self::Lint => &["po"],
+            Self::Info => &["msbt", "po"],
+            Self::Unpack | Self::Repack => &["pack"]
+        }
+    }
+}
 
-    params
+struct Options {
+    profile: Profile,
+    out: Option<PathBuf>,
+    force: bool,
+    quiet: bool,
+    source: Option<PathBuf>,
+    template: bool,
+    // otherwise taken from the region code in the file's path
+    locale: Option<&'static Locale>,
+    plurals: bool,
+    variants: bool,
+    merge: bool,
+    po: Option<PathBuf>,
+    level: u32,
+    rstb: Option<PathBuf>,
+    // resource sizes of repacked files, applied to the table once all packs are done
+    rstb_entries: Mutex<Vec<(String, u32)>>
 }
 
-pub fn po_value_from_msbt(msbt: &Msbt, message: &mut PotMessage, value: &[Token]) {
-    helper::po_value_from_msbt(&msbt, message, &value, tag_codes_to_name, new_params)
+// An input file, its path under the directory it was found in, and its output
+// path without the extension
+struct Job {
+    input: PathBuf,
+    relative: PathBuf,
+    output: PathBuf
+}
+
+// Files are taken as they are, directories are walked for files with one of
+// the extensions. Outputs go next to their inputs, or mirror them under `out`.
+fn jobs(args: &[String], extensions: &[&str], out: Option<&Path>) -> potty_msbt::Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+    let mut push = |input: &Path, relative: &Path| {
+        let output = out.map_or_else(|| input.to_path_buf(), |out| out.join(relative));
+        jobs.push(Job { input: input.to_path_buf(), relative: relative.to_path_buf(), output: output.with_extension("") });
+    };
+    for arg in args {
+        let path = Path::new(arg);
+        if !path.is_dir() {
+            push(path, Path::new(path.file_name().unwrap_or_default()));
+            continue;
+        }
+        for entry in WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+            let entry = entry.map_err(|e| Error::from(std::io::Error::from(e)).in_file(path))?;
+            let input = entry.path();
+            let wanted = input.extension().and_then(OsStr::to_str).map_or(false, |e| extensions.contains(&e));
+            if entry.file_type().is_file() && wanted {
+                push(input, input.strip_prefix(path).unwrap());
+            }
+        }
+    }
+    Ok(jobs)
 }
 
-pub fn msbt_value_from_po(message: &PotMessage) -> Vec<Token> {
-    let mut curse = std::io::Cursor::new(&message.strings[0]);
-    helper::msbt_value_from_po(&mut curse, tag_name_to_codes, new_params)
+fn invalid_input(reason: &str) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, reason.to_string()))
 }
 
-use std::path::Path;
-use std::ffi::OsStr;
+fn expect_extension(job: &Job, extension: &str) -> potty_msbt::Result<()> {
+    match job.input.extension().and_then(OsStr::to_str) {
+        Some(e) if e == extension => Ok(()),
+        _ => Err(invalid_input(&format!("expected a .{} file", extension)))
+    }
+}
+
+impl Options {
+    fn create(&self, path: &Path) -> potty_msbt::Result<File> {
+        if !self.force && path.exists() {
+            let reason = format!("{} already exists, pass --force to overwrite", path.display());
+            return Err(Error::Io(std::io::Error::new(std::io::ErrorKind::AlreadyExists, reason)));
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(File::create(path)?)
+    }
+
+    fn report(&self, job: &Job, line: impl std::fmt::Display) {
+        if !self.quiet {
+            println!("{}: {}", job.input.display(), line);
+        }
+    }
+
+    fn extract(&self, job: &Job) -> potty_msbt::Result<bool> {
+        expect_extension(job, "msbt")?;
+        let profile = &self.profile;
+        let mut reader = BufReader::new(File::open(&job.input)?);
+        if self.template {
+            let pot = potty_msbt::pot_from_msbt(&mut reader, profile)?;
+            let path = job.output.with_extension("pot");
+            pot.write(&mut self.create(&path)?)?;
+            self.report(job, path.display());
+            return Ok(true);
+        }
+        let mut pot = match self.source {
+            // a source directory mirrors the input directory
+            Some(ref source) => {
+                let source = if source.is_dir() { source.join(&job.relative) } else { source.clone() };
+                let mut source_reader = BufReader::new(File::open(&source).map_err(|e| Error::from(e).in_file(&source))?);
+                potty_msbt::po_from_msbt_pair(&mut source_reader, &mut reader, profile)?
+            },
+            None => potty_msbt::po_from_msbt(&mut reader, profile)?
+        };
+
+        let locale = self.locale.or_else(|| Locale::from_path(&job.input));
+        if self.variants {
+            potty_msbt::variants::split(&mut pot, profile);
+        }
+        match locale {
+            Some(locale) if self.plurals => potty_msbt::plurals::split(&mut pot, profile, locale),
+            Some(locale) => potty_msbt::locale::set_locale(&mut pot, locale),
+            None if self.plurals => return Err(invalid_input("no region code in the path for --plurals, pass --locale")),
+            None => {}
+        }
+
+        let path = job.output.with_extension("po");
+        let mut file_po = if self.merge && path.exists() {
+            let existing = Pot::read(&mut BufReader::new(File::open(&path)?));
+            pot = potty_msbt::merge::merge(&existing, pot);
+            File::create(&path)?
+        } else {
+            self.create(&path)?
+        };
+        pot.write(&mut file_po)?;
+        self.report(job, path.display());
+        Ok(true)
+    }
+
+    fn build(&self, job: &Job) -> potty_msbt::Result<bool> {
+        expect_extension(job, "po")?;
+        let mut reader = BufReader::new(File::open(&job.input)?);
+        let msbt = potty_msbt::msbt_from_po(&mut reader, &self.profile)?;
+        let path = job.output.with_extension("msbt");
+        msbt.write_to(BufWriter::new(self.create(&path)?))?;
+        self.report(job, path.display());
+        Ok(true)
+    }
+
+    fn verify(&self, job: &Job) -> potty_msbt::Result<bool> {
+        expect_extension(job, "msbt")?;
+        let mut reader = BufReader::new(File::open(&job.input)?);
+        let report = potty_msbt::verify_msbt(&mut reader, &self.profile)?;
+        match report.mismatch {
+            Some(mismatch) => {
+                println!("{}: {}", job.input.display(), mismatch);
+                Ok(false)
+            },
+            None => {
+                self.report(job, format!("ok ({} bytes)", report.original_len));
+                Ok(true)
+            }
+        }
+    }
+
+    fn info(&self, job: &Job) -> potty_msbt::Result<bool> {
+        let mut reader = BufReader::new(File::open(&job.input)?);
+        match job.input.extension().and_then(OsStr::to_str) {
+            Some("msbt") => {
+                let msbt = msbt::Msbt::from_reader(reader)?;
+                println!("{}: {}", job.input.display(), MsbtSummary::new(&msbt, &self.profile));
+            },
+            Some("po") => println!("{}: {}", job.input.display(), PoSummary::new(&Pot::read(&mut reader))),
+            _ => return Err(invalid_input("expected a .msbt or .po file"))
+        }
+        Ok(true)
+    }
+
+    fn lint(&self, job: &Job) -> potty_msbt::Result<bool> {
+        expect_extension(job, "po")?;
+        let mut bytes = Vec::new();
+        File::open(&job.input)?.read_to_end(&mut bytes)?;
+        let problems = potty_msbt::lint::lint(&Pot::read(&mut Cursor::new(&bytes)));
+        for problem in &problems {
+            println!("{}: {}", job.input.display(), problem);
+        }
+        // a file that doesn't build is a problem with the file, not a failure to lint it
+        let builds = match potty_msbt::msbt_from_po(&mut Cursor::new(&bytes), &self.profile) {
+            Ok(_) => true,
+            Err(e) => {
+                println!("{}", e.in_file(&job.input));
+                false
+            }
+        };
+        if problems.is_empty() && builds {
+            self.report(job, "ok");
+        }
+        Ok(problems.is_empty() && builds)
+    }
+
+    fn pack_locale(&self, job: &Job) -> potty_msbt::Result<&'static Locale> {
+        self.locale.or_else(|| Locale::from_path(&job.input))
+            .ok_or_else(|| invalid_input("no region code in the file name, pass --locale"))
+    }
+
+    // The PO tree goes in a folder named after the region, next to the pack or under --out
+    fn unpack(&self, job: &Job) -> potty_msbt::Result<bool> {
+        expect_extension(job, "pack")?;
+        let locale = self.pack_locale(job)?;
+        let pack = Sarc::from_reader(BufReader::new(File::open(&job.input)?))?;
+        let dir = job.output.parent().unwrap_or_else(|| Path::new("")).join(locale.region);
+        if !self.force && dir.exists() {
+            let reason = format!("{} already exists, pass --force to overwrite", dir.display());
+            return Err(Error::Io(std::io::Error::new(std::io::ErrorKind::AlreadyExists, reason)));
+        }
+        let paths = potty_msbt::po_tree_from_pack(&pack, locale, &dir, &self.profile)?;
+        self.report(job, format!("{} files in {}", paths.len(), dir.display()));
+        Ok(true)
+    }
+
+    // Packs without changed messages are not written, so a mod only ships the
+    // ones it modifies. A Yaz0-compressed pack is written compressed again.
+    fn repack(&self, job: &Job) -> potty_msbt::Result<bool> {
+        expect_extension(job, "pack")?;
+        let locale = self.pack_locale(job)?;
+        let bytes = std::fs::read(&job.input)?;
+        let pack = Sarc::from_bytes(&bytes)?;
+        let dir = match self.po {
+            Some(ref po) => po.join(locale.region),
+            None => job.input.parent().unwrap_or_else(|| Path::new("")).join(locale.region)
+        };
+        let (pack, changed) = potty_msbt::pack_from_po_tree(&dir, &pack, locale, self.level, &self.profile)
+            .map_err(|e| e.in_file(&dir))?;
+        if changed.is_empty() {
+            self.report(job, "unchanged");
+            return Ok(true);
+        }
+        let path = job.output.with_extension("pack");
+        let mut file = BufWriter::new(self.create(&path)?);
+        if potty_msbt::yaz0::is_compressed(&bytes) {
+            pack.write_compressed_to(&mut file, self.level)?;
+        } else {
+            pack.write_to(&mut file)?;
+        }
+        file.flush()?;
+        if self.rstb.is_some() {
+            let entries = potty_msbt::rstb_entries_for_pack(&pack, locale, &changed)?;
+            self.rstb_entries.lock().unwrap().extend(entries);
+        }
+        for name in &changed {
+            self.report(job, format!("changed {}", name));
+        }
+        self.report(job, path.display());
+        Ok(true)
+    }
+
+    // Written under --out, or in place with --force, and compressed again if it was
+    fn update_rstb(&self, path: &Path) -> potty_msbt::Result<()> {
+        let bytes = std::fs::read(path)?;
+        let mut rstb = Rstb::from_bytes(&bytes)?;
+        let mut grown = 0;
+        for (name, size) in self.rstb_entries.lock().unwrap().iter() {
+            if let Some(old) = rstb.grow(name, *size) {
+                grown += 1;
+                if !self.quiet {
+                    println!("{}: {} {} -> {}", path.display(), name, old, size);
+                }
+            }
+        }
+        if grown == 0 {
+            if !self.quiet {
+                println!("{}: unchanged", path.display());
+            }
+            return Ok(());
+        }
+        let output = self.out.as_ref().map_or_else(|| path.to_path_buf(), |out| out.join(path.file_name().unwrap_or_default()));
+        let mut file = BufWriter::new(self.create(&output)?);
+        if potty_msbt::yaz0::is_compressed(&bytes) {
+            rstb.write_compressed_to(&mut file, self.level)?;
+        } else {
+            rstb.write_to(&mut file)?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+}
 
-fn main() -> std::io::Result<()> {
-    for arg in std::env::args().skip(1) {
-        let from_type = Path::new(&arg).extension().and_then(OsStr::to_str).unwrap();
-        match from_type {
-            // msbt to po
-            "msbt" =>{
-                let file_name = &arg.strip_suffix(".msbt").unwrap();
-                let file_msbt = File::open(format!("{}.msbt", file_name))?;
-                let mut reader = BufReader::new(file_msbt);
-                let pot = potty_msbt::po_from_msbt(&mut reader, po_value_from_msbt);
-                let mut file_po = File::create(format!("{}.po", file_name))?;
-                pot.write(&mut file_po)?;
+// Runs `f` on every file across all cores, reporting errors and panics without
+// stopping. Returns how many failed and how many had problems.
+fn each_file(jobs: &[Job], f: impl Fn(&Job) -> potty_msbt::Result<bool> + Sync) -> (usize, usize) {
+    let results: Vec<Option<bool>> = jobs.par_iter().map(|job| {
+        match std::panic::catch_unwind(AssertUnwindSafe(|| f(job))) {
+            Ok(Ok(ok)) => Some(ok),
+            Ok(Err(e)) => {
+                eprintln!("{}", e.in_file(&job.input));
+                None
             },
-            // po to msbt
-            _ => {
-                let file_name = &arg.strip_suffix(".po").unwrap();
-                let file_msbt = File::open(format!("{}.po", file_name))?;
-                let mut reader = BufReader::new(file_msbt);
-                let msbt = potty_msbt::msbt_from_po(&mut reader, msbt_value_from_po);
-                let file_msbt = BufWriter::new(File::create(format!("{}.msbt", file_name)).unwrap());
-                msbt.write_to(file_msbt).unwrap();
+            Err(_) => {
+                eprintln!("{}: panicked", job.input.display());
+                None
             }
         }
+    }).collect();
+    let failed = results.iter().filter(|r| r.is_none()).count();
+    let problems = results.iter().filter(|r| **r == Some(false)).count();
+    (failed, problems)
+}
+
+// Removes "<name> <value>" from the args, returning the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let i = args.iter().position(|a| a == name)?;
+    if i + 1 == args.len() {
+        usage_error(&format!("{} needs a value", name));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Some(value)
+}
+
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let len = args.len();
+    args.retain(|a| a != name);
+    args.len() != len
+}
+
+fn usage_error(reason: &str) -> ! {
+    eprintln!("{}\n\n{}", reason, USAGE);
+    std::process::exit(EXIT_USAGE);
+}
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let command = match args.first().map(String::as_str) {
+        Some(name) => Command::parse(name).unwrap_or_else(|| usage_error(&format!("unknown command \"{}\"", name))),
+        None => usage_error("missing command")
+    };
+    args.remove(0);
+
+    let profile = match take_option(&mut args, "--profile") {
+        Some(name) => games::by_name(&name).unwrap_or_else(|| Profile::load(name)),
+        _ => games::botw()
+    };
+    let profile = profile.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(EXIT_USAGE);
+    });
+    let locale = take_option(&mut args, "--locale").map(|code| Locale::find(&code)
+        .unwrap_or_else(|| usage_error(&format!("unknown locale \"{}\"", code))));
+    let level = take_option(&mut args, "--level").map(|level| level.parse().ok()
+        .filter(|&l| l <= potty_msbt::yaz0::MAX_LEVEL)
+        .unwrap_or_else(|| usage_error(&format!("bad --level \"{}\", expected 0 to 9", level))));
+    let options = Options {
+        profile,
+        out: take_option(&mut args, "--out").map(PathBuf::from),
+        force: take_flag(&mut args, "--force"),
+        quiet: take_flag(&mut args, "--quiet"),
+        source: take_option(&mut args, "--source").map(PathBuf::from),
+        template: take_flag(&mut args, "--template"),
+        locale,
+        plurals: take_flag(&mut args, "--plurals"),
+        variants: take_flag(&mut args, "--variants"),
+        merge: take_flag(&mut args, "--merge"),
+        po: take_option(&mut args, "--po").map(PathBuf::from),
+        level: level.unwrap_or(potty_msbt::yaz0::DEFAULT_LEVEL),
+        rstb: take_option(&mut args, "--rstb").map(PathBuf::from),
+        rstb_entries: Mutex::new(Vec::new())
+    };
+    if let Some(option) = args.iter().find(|a| a.starts_with("--")) {
+        usage_error(&format!("unknown option \"{}\"", option));
+    }
+    let extracting = options.source.is_some() || options.template
+        || options.plurals || options.variants || options.merge;
+    if extracting && command != Command::Extract {
+        usage_error("--source, --template, --plurals, --variants and --merge are for extract");
+    }
+    if options.locale.is_some() && ![Command::Extract, Command::Unpack, Command::Repack].contains(&command) {
+        usage_error("--locale is for extract, unpack and repack");
+    }
+    if (options.po.is_some() || level.is_some() || options.rstb.is_some()) && command != Command::Repack {
+        usage_error("--po, --level and --rstb are for repack");
+    }
+    if args.is_empty() {
+        usage_error("no input files");
+    }
+
+    let jobs = jobs(&args, command.extensions(), options.out.as_deref()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(EXIT_FAILED);
+    });
+    let options = &options;
+    let (failed, problems) = each_file(&jobs, |job| match command {
+        Command::Extract => options.extract(job),
+        Command::Build => options.build(job),
+        Command::Verify => options.verify(job),
+        Command::Info => options.info(job),
+        Command::Lint => options.lint(job),
+        Command::Unpack => options.unpack(job),
+        Command::Repack => options.repack(job)
+    });
+    if !options.quiet && jobs.len() > 1 {
+        eprintln!("{} files: {} ok, {} with problems, {} failed",
+            jobs.len(), jobs.len() - failed - problems, problems, failed);
+    }
+    if let Some(ref path) = options.rstb {
+        if let Err(e) = options.update_rstb(path) {
+            eprintln!("{}", e.in_file(path));
+            std::process::exit(EXIT_FAILED);
+        }
+    }
+    if failed > 0 {
+        std::process::exit(EXIT_FAILED);
+    }
+    if problems > 0 {
+        std::process::exit(EXIT_PROBLEMS);
     }
-    Ok(())
 }
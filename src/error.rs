@@ -0,0 +1,73 @@
+use std::fmt;
+use std::path::PathBuf;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Msbt(msbt::Error),
+    Po(String),
+    MissingSection(&'static str),
+    MissingExtras,
+    BadExtras(String),
+    UnsupportedVersion(u32),
+    Profile(String),
+    Sarc(String),
+    Yaz0(String),
+    Rstb(String),
+    UnknownTag { tag: String, offset: usize },
+    UnbalancedTag { tag: String, offset: usize, reason: String },
+    BadParam { tag: String, param: String, value: String, offset: usize, reason: String },
+    BadTagData { tag: String, token: usize, reason: String },
+    InMessage { id: String, source: Box<Error> },
+    InFile { path: PathBuf, source: Box<Error> }
+}
+
+impl Error {
+    pub fn in_message(self, id: &str) -> Self {
+        Self::InMessage { id: id.to_string(), source: Box::new(self) }
+    }
+    pub fn in_file<P: Into<PathBuf>>(self, path: P) -> Self {
+        Self::InFile { path: path.into(), source: Box::new(self) }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Msbt(e) => write!(f, "invalid MSBT: {:?}", e),
+            Self::Po(e) => write!(f, "invalid PO: {}", e),
+            Self::MissingSection(name) => write!(f, "missing {} section", name),
+            Self::MissingExtras => write!(f, "missing _ReadOnly_MsbtExtras message"),
+            Self::BadExtras(e) => write!(f, "invalid _ReadOnly_MsbtExtras: {}", e),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported _ReadOnly_Version {}", v),
+            Self::Profile(e) => write!(f, "invalid profile: {}", e),
+            Self::Sarc(e) => write!(f, "invalid SARC: {}", e),
+            Self::Yaz0(e) => write!(f, "invalid Yaz0: {}", e),
+            Self::Rstb(e) => write!(f, "invalid RSTB: {}", e),
+            Self::UnknownTag { tag, offset } => write!(f, "unknown tag [{}] at {}", tag, offset),
+            Self::UnbalancedTag { tag, offset, reason } => write!(f, "[/{}] at {}: {}", tag, offset, reason),
+            Self::BadParam { tag, param, value, offset, reason } =>
+                write!(f, "[{}] at {}: bad {} \"{}\", {}", tag, offset, param, value, reason),
+            Self::BadTagData { tag, token, reason } => write!(f, "[{}] token {}: {}", tag, token, reason),
+            Self::InMessage { id, source } => write!(f, "msgid \"{}\": {}", id, source),
+            Self::InFile { path, source } => write!(f, "{}: {}", path.display(), source)
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<msbt::Error> for Error {
+    fn from(e: msbt::Error) -> Self {
+        Self::Msbt(e)
+    }
+}
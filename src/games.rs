@@ -0,0 +1,22 @@
+use crate::error::Result;
+use crate::profile::Profile;
+
+const BOTW_PROFILE: &str = include_str!("../profiles/botw.toml");
+const CARDBOARD_PROFILE: &str = include_str!("../profiles/cardboard.toml");
+
+// The profiles built into the crate. A loaded profile can stand in for either.
+pub fn botw() -> Result<Profile> {
+    Profile::from_toml(BOTW_PROFILE)
+}
+
+pub fn cardboard() -> Result<Profile> {
+    Profile::from_toml(CARDBOARD_PROFILE)
+}
+
+pub fn by_name(name: &str) -> Option<Result<Profile>> {
+    match name {
+        "botw" => Some(botw()),
+        "cardboard" => Some(cardboard()),
+        _ => None
+    }
+}
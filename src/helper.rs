@@ -2,30 +2,46 @@ use msbt::{Msbt, Encoding};
 use potty::PotMessage;
 use regex::Regex;
 use msbt::section::txt2::Token;
-use std::io::{Read, Seek, BufRead, Write};
+use byteordered::{ByteOrdered, Endianness};
+use std::io::{Read, Seek, Write};
+use crate::error::{Error, Result};
+use crate::profile::GameProfile;
 
-#[macro_export]
-macro_rules! tag_code_maps {
-    ($(($group_code:expr, $code:expr, $var:ident, $tag_name:expr)),*) => {
-        pub mod tag_name {
-            $(pub const $var: &str = $tag_name;)*
-        }
-        pub fn tag_codes_to_name(group_code: u16, code: u16) -> String {
-            match (group_code, code) {
-                $(($group_code, $code) => $tag_name.to_string()),*,
-                _ => format!("{:02X}:{:02X}", group_code, code)
-            }
+#[derive(Clone, Copy)]
+pub struct Codec {
+    pub endianness: Endianness,
+    pub encoding: Encoding
+}
+
+impl Codec {
+    pub fn new(endianness: Endianness, encoding: Encoding) -> Self {
+        Self{ endianness, encoding }
+    }
+    pub fn from_msbt(msbt: &Msbt) -> Self {
+        Self::new(msbt.header().endianness(), msbt.header().encoding())
+    }
+    pub fn decode(&self, b: &[u8]) -> String {
+        match self.encoding {
+            Encoding::Utf16 => String::from_utf16_lossy(
+                &b.chunks_exact(2)
+                    .map(|bs| match self.endianness {
+                        Endianness::Little => u16::from_le_bytes([bs[0], bs[1]]),
+                        Endianness::Big => u16::from_be_bytes([bs[0], bs[1]])
+                    })
+                    .collect::<Vec<u16>>()
+            ),
+            Encoding::Utf8 => String::from_utf8_lossy(b).to_string()
         }
-        pub fn tag_name_to_codes(name: &str) -> Option<(u16, u16)> {
-            match name {
-                $($tag_name => Some(($group_code, $code))),*,
-                _ => None
-            }.or_else(|| {
-                let mut group_tag = name.split(":");
-                let group_code = hex::decode(group_tag.next()?).ok()?[0];
-                let code = hex::decode(group_tag.next()?).ok()?[0];
-                Some((group_code as u16, code as u16))
-            })
+    }
+    pub fn encode(&self, s: &str) -> Vec<u8> {
+        match self.encoding {
+            Encoding::Utf16 => s.encode_utf16()
+                .flat_map(|u| match self.endianness {
+                    Endianness::Little => u.to_le_bytes(),
+                    Endianness::Big => u.to_be_bytes()
+                })
+                .collect(),
+            Encoding::Utf8 => s.as_bytes().to_vec()
         }
     }
 }
@@ -34,68 +50,65 @@ macro_rules! tag_code_maps {
 pub struct Param {
     pub name: String,
     pub value: Value,
-    pub map: Vec<(&'static str, &'static str)>
+    pub map: Vec<(String, String)>,
+    // left out of PO tags when the value equals it
+    pub default: Option<String>
 }
 
 impl Param {
     pub fn new(name: &str, value: Value) -> Self {
         Self{
             name: name.to_string(), value: value,
-            map: Vec::new()
+            map: Vec::new(), default: None
         }
     }
-    pub fn new_mapped(name: &str, value: Value, map: &[(&'static str, &'static str)]) -> Self {
+    pub fn new_mapped(name: &str, value: Value, map: &[(&str, &str)]) -> Self {
         Self{
-            map: map.to_vec(), ..Self::new(name, value)
+            map: map.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            ..Self::new(name, value)
         }
     }
-    pub fn apply_bytes<R>(&mut self, rdr: &mut R)
+    pub fn apply_bytes<R>(&mut self, rdr: &mut R, codec: &Codec) -> std::io::Result<()>
     where R: Read + Seek {
-        let mut reader = byteordered::ByteOrdered::le(rdr);
+        let mut reader = ByteOrdered::runtime(rdr, codec.endianness);
         match self.value {
-            Value::U8(ref mut n) => *n = reader.read_u8().expect("param: expected u8"),
-            Value::U16(ref mut n) => *n = reader.read_u16().expect("param: expected u16"),
+            Value::U8(ref mut n) => *n = reader.read_u8()?,
+            Value::U16(ref mut n) => *n = reader.read_u16()?,
+            Value::F32(ref mut n) => *n = reader.read_f32()?,
             Value::String(ref mut s) => {
-                let len = reader.read_u16().expect("param: expected string len byte");
+                let len = reader.read_u16()?;
                 let mut val = vec![0; len as usize];
-                reader.read_exact(&mut val).expect("param: expected string of specified len");
-                let val_u16: Vec<u16> = val.chunks_exact(2)
-                    .map(|x| u16::from_le_bytes([x[0], x[1]])).collect();
-                *s = String::from_utf16_lossy(&val_u16);
+                reader.read_exact(&mut val)?;
+                *s = codec.decode(&val);
             },
             Value::Bytes(len, ref mut v) => {
                 let mut val = vec![0; len as usize];
-                reader.read_exact(&mut val).expect("param: expected bytes of specified len");
+                reader.read_exact(&mut val)?;
                 *v = val;
             }
         }
+        Ok(())
     }
-    pub fn to_bytes(&self) -> Vec<u8> {
+    pub fn apply_str(&mut self, s: &str) -> std::result::Result<(), String> {
+        let raw = self.map.iter()
+            .find(|m| m.1 == s)
+            .map(|m| m.0.as_str()).unwrap_or(s);
+        self.value = self.value.parse(raw)?;
+        Ok(())
+    }
+    pub fn to_bytes(&self, codec: &Codec) -> Vec<u8> {
         let mut result = Vec::new();
-        let mut writer = byteordered::ByteOrdered::le(&mut result);
-        let sval = self.value.to_string();
-        let sv = self.map.iter()
-            .find(|m| m.1 == sval)
-            .map(|m| m.0);
+        let mut writer = ByteOrdered::runtime(&mut result, codec.endianness);
         match self.value {
-            Value::U8(n) => {
-                let n = sv.and_then(|qq| qq.parse().ok()).unwrap_or(n);
-                writer.write_u8(n).unwrap();
-            },
-            Value::U16(n) => {
-                let n = sv.and_then(|qq| qq.parse().ok()).unwrap_or(n);
-                writer.write_u16(n).unwrap();
-            },
+            Value::U8(n) => writer.write_u8(n).unwrap(),
+            Value::U16(n) => writer.write_u16(n).unwrap(),
+            Value::F32(n) => writer.write_f32(n).unwrap(),
             Value::String(ref s) => {
-                let bytes_u16: Vec<u16> = s.encode_utf16().collect();
-                let bytes_u8: Vec<u8> = bytes_u16.iter().flat_map(|u| Vec::from(u.to_le_bytes())).collect();
+                let bytes_u8 = codec.encode(s);
                 writer.write_u16(bytes_u8.len() as u16).unwrap();
                 writer.write(&bytes_u8).unwrap();
             },
-            Value::Bytes(_len, ref v) => {
-                let v = sv.and_then(|qq| hex::decode(qq).ok()).unwrap_or(v.to_vec());
-                writer.write_all(&v).unwrap()
-            }
+            Value::Bytes(_len, ref v) => writer.write_all(&v).unwrap()
         }
         result
     }
@@ -104,13 +117,16 @@ impl Param {
             return "".to_string()
         }
         let sval = self.value.to_string();
+        if self.default.as_ref() == Some(&sval) {
+            return "".to_string()
+        }
         let value = self.map.iter()
             .find(|m| m.0 == sval)
-            .map(|m| m.1).unwrap_or(sval.as_str());
+            .map(|m| m.1.as_str()).unwrap_or(sval.as_str());
         if value.is_empty() {
             return format!("");
         }
-        format!("{}=\"{}\" ", self.name, value)
+        format!("{}=\"{}\"", self.name, escape_param(value))
     }
 }
 
@@ -118,6 +134,7 @@ impl Param {
 pub enum Value {
     U8(u8),
     U16(u16),
+    F32(f32),
     String(String),
     Bytes(u16, Vec<u8>)
 }
@@ -127,10 +144,68 @@ impl Value {
         match self {
             Self::U8(n) => n.to_string(),
             Self::U16(n) => n.to_string(),
+            Self::F32(n) => n.to_string(),
             Self::String(s) => s.to_string(),
             Self::Bytes(_len, v) => hex::encode_upper(&v)
         }
     }
+    pub fn parse(&self, s: &str) -> std::result::Result<Self, String> {
+        match self {
+            Self::U8(_) => s.parse().map(Self::U8)
+                .map_err(|_| format!("expected a number from 0 to 255, found \"{}\"", s)),
+            Self::U16(_) => s.parse().map(Self::U16)
+                .map_err(|_| format!("expected a number from 0 to 65535, found \"{}\"", s)),
+            Self::F32(_) => s.parse().map(Self::F32)
+                .map_err(|_| format!("expected a decimal number, found \"{}\"", s)),
+            Self::String(_) => Ok(Self::String(s.to_string())),
+            Self::Bytes(_len, _v) => hex::decode(s)
+                .map(|v| Self::Bytes(v.len() as u16, v))
+                .map_err(|_| format!("expected hex bytes, found \"{}\"", s))
+        }
+    }
+}
+
+const PARAMS_RE: &str = r#"([^=\s]+)="([^"\\]*(?:\\.[^"\\]*)*)"#;
+
+fn escape_param(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+        .replace('\r', "\\r").replace('\t', "\\t")
+}
+
+fn unescape_param(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('r') => result.push('\r'),
+            Some('t') => result.push('\t'),
+            Some(c) => result.push(c),
+            None => result.push('\\')
+        }
+    }
+    result
+}
+
+// Reads up to the `]` ending a tag, ignoring any inside quoted param values.
+fn read_tag<R: Read>(rdr: &mut R) -> Option<Vec<u8>> {
+    let mut contents = Vec::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    let mut byte = [0u8];
+    loop {
+        rdr.read_exact(&mut byte).ok()?;
+        match byte[0] {
+            b']' if !in_quotes => return Some(contents),
+            b'"' if !escaped => in_quotes = !in_quotes,
+            _ => {}
+        }
+        escaped = byte[0] == b'\\' && !escaped;
+        contents.push(byte[0]);
+    }
 }
 
 #[macro_export]
@@ -157,20 +232,17 @@ macro_rules! param_bytes {
     ($name:expr, $value:expr) => { Param::new($name, Value::Bytes($value.len() as u16, $value)) };
 }
 
-fn to_le(s: &[u8]) -> Vec<u8> {
-    let s = String::from_utf8_lossy(s);
-    let bytes_u16: Vec<u16> = s.encode_utf16().collect();
-    let bytes_u8: Vec<u8> = bytes_u16.iter().flat_map(|u| Vec::from(u.to_le_bytes())).collect();
-    bytes_u8
-}
-
-pub fn msbt_value_from_po<R>(reader: &mut R, name_codes_fn: fn(&str) -> Option<(u16, u16)>, params_fn: fn(&str) -> Vec<Param>) -> Vec<Token>
+pub fn msbt_value_from_po<R>(profile: &dyn GameProfile, reader: &mut R, codec: &Codec) -> Result<Vec<Token>>
 where R: std::io::BufRead + std::io::Read + std::io::Seek {
-    let mut rdr = byteordered::ByteOrdered::le(reader);
+    let mut rdr = ByteOrdered::le(reader);
     let mut tokens: Vec<Token> = Vec::new();
     let mut prev_bytes = Vec::new();
+    // only paired tags are opened and closed
+    let paired = profile.paired_tags();
+    // with the offset each was opened at
+    let mut open_tags: Vec<(String, usize)> = Vec::new();
 
-    let params_re = Regex::new(r#"([^=\s]+)="([^"\\]*(?:\\.[^"\\]*)*)"#).unwrap();
+    let params_re = Regex::new(PARAMS_RE).unwrap();
 
     while let Ok(byte) = rdr.read_u8() {
         if byte != b'[' {
@@ -178,83 +250,378 @@ where R: std::io::BufRead + std::io::Read + std::io::Seek {
             continue;
         }
 
-        let tag_start = rdr.stream_position().unwrap();
-        let is_closing = rdr.read_u8().unwrap() == b'/';
-        if !is_closing {
-            rdr.seek(std::io::SeekFrom::Current(-1)).unwrap();
-        }
-
-        if !prev_bytes.is_empty() {
-            tokens.push(Token::Text(to_le(&prev_bytes)));
-            prev_bytes = Vec::new();
+        // "[[" is an escaped literal bracket
+        let tag_start = rdr.stream_position()?;
+        if let Ok(b'[') = rdr.read_u8() {
+            prev_bytes.push(b'[');
+            continue;
         }
+        rdr.seek(std::io::SeekFrom::Start(tag_start))?;
 
-        let mut opening_u8 = Vec::new();
-        rdr.read_until(b']', &mut opening_u8).unwrap();
+        let contents = match read_tag(&mut rdr) {
+            Some(contents) => contents,
+            _ => {
+                prev_bytes.push(b'[');
+                rdr.seek(std::io::SeekFrom::Start(tag_start))?;
+                continue
+            }
+        };
+        let offset = tag_start as usize - 1;
 
-        let cnts = String::from_utf8_lossy(&opening_u8[..opening_u8.len()-1]);
-        let mut parts = cnts.splitn(2, " ");
-        let tag_name = parts.next().unwrap();
+        let cnts = String::from_utf8_lossy(&contents);
+        let is_closing = cnts.starts_with('/');
+        let mut parts = cnts[if is_closing { 1 } else { 0 }..].splitn(2, ' ');
+        let tag_name = parts.next().unwrap_or("");
 
-        if is_closing {
-            tokens.push(Token::TagEnd);
+        let token = if is_closing {
+            // "[/name]" has to close the innermost open tag, "[/]" closes it
+            // whatever it is, or stands alone when none is open
+            let reason = match open_tags.last() {
+                Some((open, _)) if tag_name.is_empty() || open == tag_name => None,
+                Some((open, _)) if open_tags.iter().any(|(t, _)| t == tag_name) =>
+                    Some(format!("[{}] inside it is still open", open)),
+                Some((open, _)) => Some(format!("closing tag does not match open tag [{}]", open)),
+                None if tag_name.is_empty() => None,
+                None => Some("closing tag without an open tag".to_string())
+            };
+            open_tags.pop();
+            if let Some(reason) = reason {
+                return Err(Error::UnbalancedTag { tag: tag_name.to_string(), offset, reason });
+            }
+            Token::TagEnd
         } else {
-            let codes = match name_codes_fn(&tag_name) {
+            let params_str = parts.next().unwrap_or("");
+            // unknown names are literal text, unless they carry params like real markup
+            let codes = match profile.tag_name_to_codes(tag_name) {
                 Some(t) => t,
+                _ if tag_name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == ':')
+                    && params_re.is_match(params_str) => {
+                    return Err(Error::UnknownTag { tag: tag_name.to_string(), offset });
+                },
                 _ => {
                     prev_bytes.push(b'[');
-                    rdr.seek(std::io::SeekFrom::Start(tag_start)).unwrap();
+                    rdr.seek(std::io::SeekFrom::Start(tag_start))?;
                     continue
                 }
             };
-            let mut params = params_fn(tag_name);
-            let params_str = parts.next().unwrap();
+            let mut params = profile.new_params(tag_name);
+            // "bytes" holds whatever the typed params don't cover, or all of it
+            // for tags that were typed after the PO was written
+            let given: Vec<String> = params_re.captures_iter(params_str).map(|cap| cap[1].to_string()).collect();
+            if !params.iter().any(|p| p.name == "bytes") && given.iter().any(|n| n == "bytes") {
+                if !params.iter().any(|p| given.contains(&p.name)) {
+                    params.clear();
+                }
+                params.push(param_bytes!("bytes", Vec::<u8>::new()));
+            }
+            if params.is_empty() {
+                params.push(param_bytes!("bytes", Vec::<u8>::new()));
+            }
             for cap in params_re.captures_iter(params_str) {
                 let pp = params.iter_mut().find(|p| p.name == &cap[1]);
                 if let Some(p) = pp {
-                    if let Value::String(ref mut s) = p.value {
-                        *s = cap[2].replace("\\\"", "\"")
-                            .replace("\\r", "\r").replace("\\t", "\t").to_string();
-                    }
+                    let value = unescape_param(&cap[2]);
+                    p.apply_str(&value).map_err(|reason| Error::BadParam {
+                        tag: tag_name.to_string(), param: p.name.clone(), value, offset, reason
+                    })?;
                 }
             }
-            tokens.push(Token::TagStart(codes.0, codes.1, params.iter().flat_map(|p| p.to_bytes()).collect()));
+            if paired.iter().any(|p| p == tag_name) {
+                open_tags.push((tag_name.to_string(), offset));
+            }
+            Token::TagStart(codes.0, codes.1, params.iter().flat_map(|p| p.to_bytes(codec)).collect())
+        };
+
+        if !prev_bytes.is_empty() {
+            tokens.push(Token::Text(profile.text_to_msbt(codec, &String::from_utf8_lossy(&prev_bytes))));
+            prev_bytes = Vec::new();
+        }
+        tokens.push(token);
+    }
+
+    if let Some((tag, start)) = open_tags.pop() {
+        let reason = format!("[{}] at {} is still open at the end of the message", tag, start);
+        return Err(Error::UnbalancedTag { tag, offset: rdr.stream_position()? as usize, reason });
+    }
+    tokens.push(Token::Text(profile.text_to_msbt(codec, &String::from_utf8_lossy(&prev_bytes))));
+    Ok(tokens)
+}
+
+pub fn format_tag(name: &str, params: &[Param]) -> String {
+    let params: Vec<String> = params.iter()
+        .map(|p| p.to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    if params.is_empty() {
+        format!("[{}]", name)
+    } else {
+        format!("[{} {}]", name, params.join(" "))
+    }
+}
+
+pub fn escape_text(s: &str) -> String {
+    s.replace('[', "[[")
+}
+
+pub fn unescape_text(s: &str) -> String {
+    s.replace("[[", "[")
+}
+
+// An opening tag in PO text, `start..end` covering its brackets
+pub struct TagSpan {
+    pub start: usize,
+    pub end: usize,
+    pub params: Vec<(String, String)>
+}
+
+pub fn find_tags(s: &str, name: &str) -> Vec<TagSpan> {
+    let params_re = Regex::new(PARAMS_RE).unwrap();
+    let bytes = s.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'[' {
+            i += 1;
+            continue;
+        }
+        if bytes.get(i + 1) == Some(&b'[') {
+            i += 2;
+            continue;
         }
+        let contents = match read_tag(&mut &bytes[i + 1..]) {
+            Some(contents) => contents,
+            _ => break
+        };
+        let end = i + contents.len() + 2;
+        let contents = String::from_utf8_lossy(&contents);
+        let mut parts = contents.splitn(2, ' ');
+        if parts.next() != Some(name) {
+            i += 1;
+            continue;
+        }
+        let params = params_re.captures_iter(parts.next().unwrap_or(""))
+            .map(|cap| (cap[1].to_string(), unescape_param(&cap[2])))
+            .collect();
+        spans.push(TagSpan { start: i, end, params });
+        i = end;
     }
+    spans
+}
 
-    tokens.push(Token::Text(to_le(&prev_bytes)));
-    tokens
+// Names of the tags in PO text in order, closing tags with their "/"
+pub fn tag_names(s: &str) -> Vec<String> {
+    let bytes = s.as_bytes();
+    let mut names = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'[' {
+            i += 1;
+            continue;
+        }
+        if bytes.get(i + 1) == Some(&b'[') {
+            i += 2;
+            continue;
+        }
+        let contents = match read_tag(&mut &bytes[i + 1..]) {
+            Some(contents) => contents,
+            _ => break
+        };
+        i += contents.len() + 2;
+        let contents = String::from_utf8_lossy(&contents);
+        names.push(contents.split(' ').next().unwrap_or_default().to_string());
+    }
+    names
 }
 
-pub fn po_value_from_msbt(msbt: &Msbt, message: &mut PotMessage, value: &[Token], codes_name_fn: fn(u16, u16) -> String, params_fn: fn(&str) -> Vec<Param>) {
-    let mut name = "".to_string();
-    let result = value.iter().map(|t| {
+pub fn po_value_from_msbt(profile: &dyn GameProfile, msbt: &Msbt, message: &mut PotMessage, value: &[Token]) -> Result<()> {
+    let codec = Codec::from_msbt(msbt);
+    let paired = profile.paired_tags();
+    let mut open_tags: Vec<String> = Vec::new();
+    let mut result = String::new();
+    for (i, t) in value.iter().enumerate() {
         match t {
             Token::TagStart(group, tag, _params) => {
-                name = codes_name_fn(*group, *tag);
+                let name = profile.tag_codes_to_name(*group, *tag);
                 let mut rdr = std::io::Cursor::new(_params);
-                let mut params = params_fn(&name);
+                let mut params = profile.new_params(&name);
                 if _params.len() > 0 && params.len() == 0 {
                     params.push(param_bytes!("bytes", vec![0; _params.len()]));
                 }
                 for p in &mut params {
-                    p.apply_bytes(&mut rdr);
+                    p.apply_bytes(&mut rdr, &codec).map_err(|e| Error::BadTagData {
+                        tag: name.clone(), token: i, reason: format!("{}: {}", p.name, e)
+                    })?;
                 }
-                format!("[{} {}]", name, params.iter().map(|p| p.to_string()).collect::<String>())
-            },
-            Token::Text(b) => {
-                match msbt.header().encoding() {
-                    Encoding::Utf16 => String::from_utf16_lossy(
-                        &b.chunks(2)
-                            .map(|bs| u16::from_le_bytes([bs[0], bs[1]]))
-                            .collect::<Vec<u16>>()
-                    ),
-                    _ => String::from_utf8_lossy(b).to_string()
+                let rest = &_params[rdr.position() as usize..];
+                if !rest.is_empty() {
+                    params.push(param_bytes!("bytes", rest.to_vec()));
+                }
+                result.push_str(&format_tag(&name, &params));
+                if paired.contains(&name) {
+                    open_tags.push(name);
                 }
             },
-            Token::TagEnd => format!("[/{} ]", name),
-            _ => "".to_string()
+            Token::Text(b) => result.push_str(&profile.text_to_po(&codec, b)),
+            // an end without an open paired tag is written as "[/]"
+            Token::TagEnd => result.push_str(&format!("[/{}]", open_tags.pop().unwrap_or_default())),
+            _ => {}
         }
-    }).collect::<String>();
+    }
     message.strings = vec![result];
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+    use std::io::Cursor;
+
+    const PROFILE: &str = r#"
+        name = "test"
+
+        [maps]
+        colors = { "0" = "red", "65535" = "unset" }
+
+        [[tags]]
+        group = 0
+        code = 3
+        name = "Color"
+        params = [ { name = "name", type = "u16", map = "colors" } ]
+        paired = true
+
+        [[tags]]
+        group = 0
+        code = 2
+        name = "Size"
+        params = [ { name = "percent", type = "u16" } ]
+        paired = true
+
+        [[tags]]
+        group = 1
+        code = 0
+        name = "Pause"
+        params = [ { name = "frames", type = "u16" } ]
+
+        [[tags]]
+        group = 1
+        code = 1
+        name = "Speed"
+        params = [ { name = "rate", type = "f32" } ]
+
+        [[tags]]
+        group = 2
+        code = 0
+        name = "Ruby"
+        params = [ { name = "width", type = "u16" }, { name = "rt", type = "string" } ]
+    "#;
+
+    fn codec() -> Codec {
+        Codec::new(Endianness::Little, Encoding::Utf8)
+    }
+
+    fn parse(text: &str) -> Result<Vec<Token>> {
+        let profile = Profile::from_toml(PROFILE).unwrap();
+        msbt_value_from_po(&profile, &mut Cursor::new(text), &codec())
+    }
+
+    fn tag_start(token: &Token) -> (u16, u16, &[u8]) {
+        match token {
+            Token::TagStart(group, code, params) => (*group, *code, params.as_slice()),
+            _ => panic!("expected a tag")
+        }
+    }
+
+    fn text(token: &Token) -> &[u8] {
+        match token {
+            Token::Text(bytes) => bytes,
+            _ => panic!("expected text")
+        }
+    }
+
+    fn unbalanced(text: &str) -> (String, usize, String) {
+        match parse(text) {
+            Err(Error::UnbalancedTag { tag, offset, reason }) => (tag, offset, reason),
+            other => panic!("expected an unbalanced tag in {:?}, got {:?}", text, other.map(|_| ()))
+        }
+    }
+
+    #[test]
+    fn closes_paired_tags() {
+        let tokens = parse(r#"a[Color name="red"]b[Size percent="50"]c[/Size][/]d"#).unwrap();
+        assert_eq!(tokens.iter().filter(|t| matches!(t, Token::TagEnd)).count(), 2);
+        // an end without an open tag stays as it is
+        assert!(matches!(parse("a[/]b").unwrap()[1], Token::TagEnd));
+        // tags that aren't paired don't need closing
+        assert!(parse(r#"[Pause frames="30"]a"#).is_ok());
+    }
+
+    #[test]
+    fn reports_unbalanced_tags() {
+        let (tag, offset, reason) = unbalanced(r#"[Color name="red"]a[Size percent="50"]b[/Color]"#);
+        assert_eq!((tag.as_str(), offset), ("Color", 39));
+        assert_eq!(reason, "[Size] inside it is still open");
+        let (tag, _, reason) = unbalanced(r#"[Color name="red"]a[/Size]"#);
+        assert_eq!(tag, "Size");
+        assert_eq!(reason, "closing tag does not match open tag [Color]");
+        let (tag, offset, reason) = unbalanced("a[/Color]");
+        assert_eq!((tag.as_str(), offset), ("Color", 1));
+        assert_eq!(reason, "closing tag without an open tag");
+    }
+
+    #[test]
+    fn reports_tags_open_at_the_end() {
+        let (tag, offset, reason) = unbalanced(r#"a[Color name="red"]b[Size percent="50"]c[/Size]"#);
+        assert_eq!((tag.as_str(), offset), ("Color", 47));
+        assert_eq!(reason, "[Color] at 1 is still open at the end of the message");
+    }
+
+    #[test]
+    fn escapes_brackets() {
+        let text = "a [b] [[c";
+        assert_eq!(escape_text(text), "a [[b] [[[[c");
+        assert_eq!(unescape_text(&escape_text(text)), text);
+        assert_eq!(tag_names(r#"[[x] [Color name="red"]y[/Color] [[[Pause]"#), vec!["Color", "/Color", "Pause"]);
+    }
+
+    #[test]
+    fn reads_escaped_brackets_before_tags() {
+        let tokens = parse(r#"[[[Pause frames="30"]x"#).unwrap();
+        assert_eq!(text(&tokens[0]), b"[");
+        assert_eq!(tag_start(&tokens[1]), (1, 0, &[30, 0][..]));
+        assert_eq!(text(&tokens[2]), b"x");
+        // an escaped bracket makes the rest text
+        let tokens = parse(r#"[[Pause frames="30"]"#).unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(text(&tokens[0]), br#"[Pause frames="30"]"#);
+        // as do unknown names without params and brackets that aren't closed
+        assert_eq!(text(&parse("[Nope] [x").unwrap()[0]), b"[Nope] [x");
+    }
+
+    #[test]
+    fn reads_typed_params() {
+        let tokens = parse(r#"[Color name="unset"][Color name="2"][/Color][/Color][Speed rate="1.5"][Ruby width="4" rt="a\"]b"]"#).unwrap();
+        assert_eq!(tag_start(&tokens[0]), (0, 3, &[0xFF, 0xFF][..]));
+        assert_eq!(tag_start(&tokens[1]), (0, 3, &[2, 0][..]));
+        assert_eq!(tag_start(&tokens[4]), (1, 1, &1.5f32.to_le_bytes()[..]));
+        assert_eq!(tag_start(&tokens[5]), (2, 0, &[4, 0, 4, 0, b'a', b'"', b']', b'b'][..]));
+        // raw bytes from before a tag was typed
+        assert_eq!(tag_start(&parse(r#"[Pause bytes="0A00"]"#).unwrap()[0]), (1, 0, &[0x0A, 0][..]));
+        assert_eq!(format_tag("Ruby", &[Param::new("rt", Value::String("say \"hi\"".to_string()))]), r#"[Ruby rt="say \"hi\""]"#);
+    }
+
+    #[test]
+    fn reports_unknown_tags_and_bad_params() {
+        match parse(r#"ab[Nope value="1"]"#) {
+            Err(Error::UnknownTag { tag, offset }) => assert_eq!((tag.as_str(), offset), ("Nope", 2)),
+            _ => panic!("expected an unknown tag")
+        }
+        for &(text, value) in &[(r#"[Pause frames="x"]"#, "x"), (r#"[Pause frames="70000"]"#, "70000"),
+                                (r#"[Speed rate="fast"]"#, "fast"), (r#"[Color name="purple"]"#, "purple")] {
+            match parse(text) {
+                Err(Error::BadParam { value: v, offset, .. }) => assert_eq!((v.as_str(), offset), (value, 0)),
+                _ => panic!("expected a bad param in {}", text)
+            }
+        }
+    }
 }
@@ -0,0 +1,94 @@
+use crate::locale;
+use crate::profile::GameProfile;
+use msbt::{Encoding, Msbt};
+use msbt::section::txt2::Token;
+use potty::Pot;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct MsbtSummary {
+    pub labels: usize,
+    pub groups: usize,
+    pub big_endian: bool,
+    pub utf16: bool,
+    pub sections: Vec<&'static str>,
+    // uses of each tag by name
+    pub tags: BTreeMap<String, usize>
+}
+
+impl MsbtSummary {
+    pub fn new(msbt: &Msbt, profile: &dyn GameProfile) -> Self {
+        let sections = [
+            ("LBL1", msbt.lbl1().is_some()),
+            ("TXT2", msbt.txt2().is_some()),
+            ("ATR1", msbt.atr1().is_some()),
+            ("ATO1", msbt.ato1().is_some()),
+            ("TSY1", msbt.tsy1().is_some()),
+            ("NLI1", msbt.nli1().is_some())
+        ];
+        let mut tags = BTreeMap::new();
+        for token in msbt.txt2().iter().flat_map(|t| t.values()).flatten() {
+            if let Token::TagStart(group_code, code, _) = token {
+                *tags.entry(profile.tag_codes_to_name(*group_code, *code)).or_insert(0) += 1;
+            }
+        }
+        Self {
+            labels: msbt.lbl1().map_or(0, |l| l.labels().len()),
+            groups: msbt.lbl1().map_or(0, |l| l.groups().len()),
+            big_endian: matches!(msbt.header().endianness(), byteordered::Endianness::Big),
+            utf16: msbt.header().encoding() == Encoding::Utf16,
+            sections: sections.iter().filter(|(_, present)| *present).map(|(name, _)| *name).collect(),
+            tags
+        }
+    }
+}
+
+impl fmt::Display for MsbtSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} labels in {} groups, {} {}, sections {}",
+            self.labels, self.groups,
+            if self.utf16 { "UTF-16" } else { "UTF-8" },
+            if self.big_endian { "big endian" } else { "little endian" },
+            self.sections.join(" "))?;
+        if !self.tags.is_empty() {
+            let tags: Vec<String> = self.tags.iter().map(|(name, count)| format!("{} x{}", name, count)).collect();
+            write!(f, ", tags {}", tags.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PoSummary {
+    pub entries: usize,
+    pub translated: usize,
+    pub fuzzy: usize,
+    pub obsolete: usize,
+    pub language: Option<String>
+}
+
+impl PoSummary {
+    pub fn new(pot: &Pot) -> Self {
+        let entries: Vec<_> = pot.messages.iter().filter(|m| !locale::is_header(m)).collect();
+        let language = locale::header_fields(&pot.messages).into_iter()
+            .find(|(name, _)| name == "Language")
+            .map(|(_, value)| value)
+            .filter(|value| !value.is_empty());
+        Self {
+            entries: entries.iter().filter(|m| !m.obsolete).count(),
+            translated: entries.iter().filter(|m| !m.obsolete && m.strings.iter().any(|s| !s.is_empty())).count(),
+            fuzzy: entries.iter().filter(|m| !m.obsolete && m.flags.iter().any(|f| f == "fuzzy")).count(),
+            obsolete: entries.iter().filter(|m| m.obsolete).count(),
+            language
+        }
+    }
+}
+
+impl fmt::Display for PoSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} entries, {} translated, {} fuzzy, {} obsolete, language {}",
+            self.entries, self.translated, self.fuzzy, self.obsolete,
+            self.language.as_deref().unwrap_or("unknown"))
+    }
+}
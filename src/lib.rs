@@ -1,92 +1,469 @@
 mod model;
+mod attributes;
+mod error;
+pub mod games;
 pub mod helper;
+pub mod info;
+pub mod lint;
+pub mod locale;
+pub mod merge;
+pub mod plurals;
+pub mod profile;
+pub mod rstb;
+pub mod sarc;
+pub mod variants;
+pub mod verify;
+pub mod yaz0;
+
+pub use crate::error::{Error, Result};
+pub use crate::locale::Locale;
+pub use crate::profile::{GameProfile, Profile};
+pub use crate::rstb::Rstb;
+pub use crate::sarc::Sarc;
 
-use byteordered::{Endianness};
 use potty::{Pot, PotMessage};
-use msbt::{Msbt, Encoding, builder::MsbtBuilder, section::txt2::Token};
-use crate::model::{MsbtInfo, Nli1};
-use std::io::{Read, Seek};
+use msbt::{Msbt, builder::MsbtBuilder};
+use crate::model::{MsbtInfo, MsbtInfoV1, Atr1};
+use byteordered::ByteOrdered;
+use crate::helper::Codec;
+use std::io::{Read, Seek, Cursor};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
 const EXTRAS_ID: &str = "_ReadOnly_MsbtExtras";
 const VERSION_ID: &str = "_ReadOnly_Version";
+// 1 is the base64 bincode extras, 2 the text form in model.rs
+pub(crate) const VERSION: u32 = 2;
+// extracted comments holding the value of a reserved entry from a template
+const VALUE_PREFIX: &str = "value: ";
+
+pub fn po_from_msbt<R: Read + Seek>(reader: &mut R, profile: &dyn GameProfile) -> Result<Pot> {
+    let msbt = Msbt::from_reader(reader)?;
+    pot_from_msbts(&msbt, None, profile)
+}
+
+// Labels go in msgctxt, source text in msgid and target text in msgstr.
+pub fn po_from_msbt_pair<R: Read + Seek, S: Read + Seek>(source_reader: &mut R, target_reader: &mut S, profile: &dyn GameProfile) -> Result<Pot> {
+    let source = Msbt::from_reader(source_reader)?;
+    let target = Msbt::from_reader(target_reader)?;
+    pot_from_msbts(&target, Some(&source), profile)
+}
 
-pub fn po_from_msbt<R: Read + Seek>(reader: &mut R, parse_fn: fn (&Msbt, &mut PotMessage, &[Token])) -> Pot {
-    let msbt = Msbt::from_reader(reader);
-    let msbt = msbt.unwrap();
-    let lbl1 = msbt.lbl1().unwrap();
-    let txt2 = msbt.txt2().unwrap();
+// Template tools empty every msgstr, so the extras and version are kept in
+// extracted comments instead, which they carry over
+pub fn pot_from_msbt<R: Read + Seek>(reader: &mut R, profile: &dyn GameProfile) -> Result<Pot> {
+    let msbt = Msbt::from_reader(reader)?;
+    let mut pot = pot_from_msbts(&msbt, Some(&msbt), profile)?;
+    for message in pot.messages.iter_mut().filter(|m| !locale::is_header(m)) {
+        if message.context.is_none() {
+            let value = message.strings.first().cloned().unwrap_or_default();
+            message.extracted_comments.extend(value.lines().map(|line| format!("{}{}", VALUE_PREFIX, line)));
+        }
+        message.strings = vec!["".to_string()];
+    }
+    Ok(pot)
+}
+
+fn texts_from_msbt(msbt: &Msbt, profile: &dyn GameProfile) -> Result<HashMap<String, String>> {
+    let txt2 = msbt.txt2().ok_or(Error::MissingSection("TXT2"))?;
+    let lbl1 = msbt.lbl1().ok_or(Error::MissingSection("LBL1"))?;
+    lbl1.labels().iter().enumerate().map(|(i, label)| {
+        let mut message = PotMessage::new();
+        helper::po_value_from_msbt(profile, msbt, &mut message, txt2.values().get(i).map_or(&[], Vec::as_slice))
+            .map_err(|e| e.in_message(label.name()))?;
+        Ok((label.name().to_string(), message.strings.into_iter().next().unwrap_or_default()))
+    }).collect()
+}
+
+fn pot_from_msbts(msbt: &Msbt, source: Option<&Msbt>, profile: &dyn GameProfile) -> Result<Pot> {
+    let source_texts = source.map(|s| texts_from_msbt(s, profile)).transpose()?;
+    let lbl1 = msbt.lbl1().ok_or(Error::MissingSection("LBL1"))?;
+    let txt2 = msbt.txt2().ok_or(Error::MissingSection("TXT2"))?;
+    let codec = Codec::from_msbt(msbt);
+    let attributes = msbt.atr1()
+        .and_then(|a| attributes::decode(a.unknown_bytes(), &codec))
+        .unwrap_or_default();
     let mut pot = Pot::new();
+    pot.messages.push(locale::new_header(profile.name()));
 
     for (i, label) in lbl1.labels().iter().enumerate() {
         let mut message = PotMessage::new();
-        parse_fn(&msbt, &mut message, &txt2.values()[i]);
-        message.id = Some(label.name().to_string());
+        helper::po_value_from_msbt(profile, msbt, &mut message, txt2.values().get(i).map_or(&[], Vec::as_slice))
+            .map_err(|e| e.in_message(label.name()))?;
+        match source_texts {
+            // msgid can't be empty, so the label stands in for missing source text
+            Some(ref texts) => {
+                message.context = Some(label.name().to_string());
+                message.id = Some(texts.get(label.name())
+                    .filter(|t| !t.is_empty())
+                    .cloned()
+                    .unwrap_or_else(|| label.name().to_string()));
+            },
+            None => {
+                message.id = Some(label.name().to_string());
+                merge::record_source(&mut message);
+            }
+        }
+        if let Some(attribute) = attributes.get(i).filter(|a| !a.is_empty()) {
+            message.extracted_comments.push(format!("{}{}", attributes::COMMENT_PREFIX, attribute));
+        }
         pot.messages.push(message);
     }
 
+    let labels: Vec<String> = lbl1.labels().iter().map(|l| l.name().to_string()).collect();
     let extras_obj = MsbtInfo{
         group_count: lbl1.groups().len() as u32,
-        atr1: msbt.atr1().map(|a| a.unknown_bytes().to_vec()),
+        endianness: msbt.header().endianness().into(),
+        encoding: msbt.header().encoding().into(),
+        atr1: msbt.atr1().map(|a| match attributes::layout(a.unknown_bytes(), &codec) {
+            Some(layout) => Atr1::Attributes(layout),
+            _ => Atr1::Bytes(a.unknown_bytes().to_vec())
+        }),
         ato1: msbt.ato1().map(|a| a.unknown_bytes().to_vec()),
-        tsy1: msbt.tsy1().map(|a| a.unknown_bytes().to_vec()),
-        nli1: msbt.nli1().map(|a| Nli1 {
-            id_count: a.id_count(),
-            global_ids: a.global_ids().clone(),
+        tsy1: msbt.tsy1().map(|a| {
+            let mut rdr = ByteOrdered::runtime(Cursor::new(a.unknown_bytes()), codec.endianness);
+            labels.iter()
+                .map_while(|label| rdr.read_u32().ok().map(|style| (label.clone(), style)))
+                .collect()
         }),
+        nli1: msbt.nli1().map(|a| a.global_ids().iter()
+            .filter_map(|(&id, &index)| labels.get(index as usize).map(|label| (label.clone(), id)))
+            .collect()),
     };
 
     let mut extras_msg = PotMessage::new();
     extras_msg.id = Some(EXTRAS_ID.to_string());
-    let binny = bincode::serialize(&extras_obj).unwrap();
-    extras_msg.strings.push(base64::encode(binny));
+    extras_msg.strings.push(extras_obj.to_text());
     pot.messages.push(extras_msg);
 
     let mut version_msg = PotMessage::new();
     version_msg.id = Some(VERSION_ID.to_string());
-    version_msg.strings.push(1.to_string());
+    version_msg.strings.push(VERSION.to_string());
     pot.messages.push(version_msg);
 
-    pot
+    Ok(pot)
 }
 
-pub fn msbt_from_po<R: Read + Seek>(mut reader: &mut R, parse_fn: fn (&PotMessage) -> Vec<Token>) -> Msbt {
+// The value of a reserved entry, which a PO made from a template has in comments
+fn reserved_value(message: &PotMessage) -> Option<String> {
+    let value = message.strings.first()?;
+    if !value.is_empty() {
+        return Some(value.clone());
+    }
+    Some(message.extracted_comments.iter()
+        .filter_map(|c| c.strip_prefix(VALUE_PREFIX))
+        .map(|line| format!("{}\n", line))
+        .collect())
+}
+
+// The _ReadOnly_Version of a PO file, if it has a readable one
+pub(crate) fn po_version(messages: &[PotMessage]) -> Option<u32> {
+    messages.iter()
+        .find(|m| !m.obsolete && m.context.is_none() && m.id.as_deref() == Some(VERSION_ID))
+        .and_then(reserved_value)
+        .and_then(|value| value.trim().parse().ok())
+}
+
+// Bilingual entries are normalised to a label in msgid and the text in msgstr.
+// Untranslated ones fall back to the source text, unless msgid is just the label.
+fn resolve_message(message: &PotMessage) -> PotMessage {
+    let mut resolved = message.clone();
+    if let Some(ref label) = message.context {
+        let id = message.id.clone().unwrap_or_default();
+        let text = message.strings.first().cloned().unwrap_or_default();
+        resolved.strings = vec![if text.is_empty() && &id != label { id } else { text }];
+        resolved.id = Some(label.clone());
+        resolved.context = None;
+    }
+    resolved
+}
+
+pub fn msbt_from_po<R: Read + Seek>(mut reader: &mut R, profile: &dyn GameProfile) -> Result<Msbt> {
     let pot = Pot::read(&mut reader);
-    let mut msbt_extras: Option<MsbtInfo> = None;
-    let mut _potty_version = "";
+    locale::validate(&pot.messages)?;
+    let messages = pot.messages.iter()
+        .filter(|m| !locale::is_header(m) && !m.obsolete)
+        .map(|m| plurals::fold(m, profile)
+            .map(|m| resolve_message(&m))
+            .map_err(|e| e.in_message(m.context.as_ref().or(m.id.as_ref()).map_or("", String::as_str))))
+        .collect::<Result<Vec<PotMessage>>>()?;
+    let messages = variants::join(messages, profile)?;
+    let mut extras_value = None;
+    let mut potty_version = 1;
 
-    for message in &pot.messages {
+    for message in &messages {
         let id = match message.id {
             Some(ref id) => id,
             _ => continue
         };
-        let value: String = message.strings[0].clone();
+        let value = match reserved_value(message) {
+            Some(value) => value,
+            _ => return Err(Error::Po("expected a msgstr".to_string()).in_message(id))
+        };
         if id == EXTRAS_ID {
-            msbt_extras = Some(bincode::deserialize(&base64::decode(value).unwrap()).unwrap());
+            extras_value = Some(value);
         } else if id == VERSION_ID {
-            _potty_version = &value;
+            potty_version = value.trim().parse()
+                .map_err(|_| Error::Po(format!("expected a number, found \"{}\"", value)).in_message(id))?;
         }
     }
 
-    let msbt_extras = msbt_extras.unwrap();
-    let mut builder = MsbtBuilder::new(Endianness::Little, Encoding::Utf16, Some(msbt_extras.group_count));
+    let labels: Vec<String> = messages.iter()
+        .map(|m| m.id.clone().unwrap_or_default())
+        .filter(|id| id != EXTRAS_ID && id != VERSION_ID)
+        .collect();
+    let extras_value = extras_value.ok_or(Error::MissingExtras)?;
+    let (msbt_extras, migrated_attributes) = match potty_version {
+        1 => {
+            let bad_extras = |e: &dyn std::fmt::Display| Error::BadExtras(e.to_string());
+            let binny = base64::decode(&extras_value).map_err(|e| bad_extras(&e))?;
+            bincode::deserialize::<MsbtInfoV1>(&binny).map_err(|e| bad_extras(&e))?.into_info(&labels)
+        },
+        VERSION => (MsbtInfo::from_text(&extras_value).map_err(Error::BadExtras)?, None),
+        _ => return Err(Error::UnsupportedVersion(potty_version))
+    };
+    // ATR1 has an entry per message, which must still be one per label
+    let atr1_mismatch = |count: usize| Error::BadExtras(format!(
+        "ATR1 has {} entries for {} messages, so labels can't be added or removed in this file", count, labels.len()));
+    let codec = Codec::new(msbt_extras.endianness.into(), msbt_extras.encoding.into());
+    let mut builder = MsbtBuilder::new(codec.endianness, codec.encoding, Some(msbt_extras.group_count));
     if let Some(unknown_bytes) = msbt_extras.ato1 {
         builder = builder.ato1(msbt::section::Ato1::new_unlinked(unknown_bytes));
     }
-    if let Some(unknown_bytes) = msbt_extras.atr1 {
-    builder = builder.atr1(msbt::section::Atr1::new_unlinked(unknown_bytes));
+    if let Some(atr1) = msbt_extras.atr1 {
+        let unknown_bytes = match atr1 {
+            // the current version puts every non-empty value in a comment,
+            // version 1 only has them by message index
+            Atr1::Attributes(layout) => {
+                let values: Vec<String> = match migrated_attributes {
+                    Some(values) if values.len() != labels.len() => return Err(atr1_mismatch(values.len())),
+                    Some(values) => values,
+                    None => messages.iter()
+                        .filter(|m| !matches!(m.id.as_deref().unwrap_or(""), EXTRAS_ID | VERSION_ID))
+                        .map(|m| m.extracted_comments.iter()
+                            .find_map(|c| c.strip_prefix(attributes::COMMENT_PREFIX))
+                            .unwrap_or("").to_string())
+                        .collect()
+                };
+                attributes::encode(&layout, &values, &codec)
+                    .map_err(|(i, e)| Error::Po(e).in_message(&labels[i]))?
+            },
+            Atr1::Bytes(unknown_bytes) => match attributes::count(&unknown_bytes, &codec) {
+                Some(count) if count as usize != labels.len() => return Err(atr1_mismatch(count as usize)),
+                _ => unknown_bytes
+            }
+        };
+        builder = builder.atr1(msbt::section::Atr1::new_unlinked(unknown_bytes));
     }
-    if let Some(unknown_bytes) = msbt_extras.tsy1 {
+    if let Some(styles) = msbt_extras.tsy1 {
+        let mut unknown_bytes = Vec::new();
+        let mut writer = ByteOrdered::runtime(&mut unknown_bytes, codec.endianness);
+        for label in &labels {
+            writer.write_u32(styles.get(label).copied().unwrap_or(0)).unwrap();
+        }
         builder = builder.tsy1(msbt::section::Tsy1::new_unlinked(unknown_bytes));
     }
-    if let Some(nli1) = msbt_extras.nli1 {
-        builder = builder.nli1(msbt::section::Nli1::new_unlinked(nli1.id_count, nli1.global_ids));
+    if let Some(ids) = msbt_extras.nli1 {
+        let global_ids: BTreeMap<u32, u32> = labels.iter().enumerate()
+            .filter_map(|(index, label)| ids.get(label).map(|&id| (id, index as u32)))
+            .collect();
+        builder = builder.nli1(msbt::section::Nli1::new_unlinked(global_ids.len() as u32, global_ids));
     }
-    for message in &pot.messages {
+    for message in &messages {
         let id = message.id.clone().unwrap_or_default();
         if id != EXTRAS_ID && id != VERSION_ID {
-            builder = builder.add_label(id, parse_fn(&message));
+            let text = message.strings.first().map(String::as_str).unwrap_or_default();
+            let value = helper::msbt_value_from_po(profile, &mut Cursor::new(text), &codec)
+                .map_err(|e| e.in_message(&id))?;
+            builder = builder.add_label(id, value);
         }
     }
 
-    builder.build()
+    Ok(builder.build())
+}
+
+pub fn verify_msbt<R: Read + Seek>(reader: &mut R, profile: &dyn GameProfile) -> Result<verify::Report> {
+    let mut original_bytes = Vec::new();
+    reader.read_to_end(&mut original_bytes)?;
+
+    let pot = po_from_msbt(&mut Cursor::new(&original_bytes), profile)?;
+    let mut po_bytes = Vec::new();
+    pot.write(&mut po_bytes)?;
+
+    let rebuilt = msbt_from_po(&mut Cursor::new(&po_bytes), profile)?;
+    let mut rebuilt_bytes = Vec::new();
+    rebuilt.write_to(&mut rebuilt_bytes)?;
+
+    let original = Msbt::from_reader(Cursor::new(&original_bytes))?;
+    let rebuilt = Msbt::from_reader(Cursor::new(&rebuilt_bytes))?;
+    for msbt in &[&original, &rebuilt] {
+        msbt.lbl1().ok_or(Error::MissingSection("LBL1"))?;
+        msbt.txt2().ok_or(Error::MissingSection("TXT2"))?;
+    }
+    Ok(verify::compare(&original, &original_bytes, &rebuilt, &rebuilt_bytes))
+}
+
+// Every MSBT in the archive as a PO, with its archive path ending in .po
+pub fn pos_from_sarc(archive: &Sarc, profile: &dyn GameProfile) -> Result<Vec<(PathBuf, Pot)>> {
+    archive.files.iter()
+        .filter(|f| f.name.ends_with(".msbt"))
+        .map(|f| {
+            let pot = po_from_msbt(&mut Cursor::new(&f.data), profile).map_err(|e| e.in_file(&f.name))?;
+            Ok((Path::new(&f.name).with_extension("po"), pot))
+        })
+        .collect()
+}
+
+// Writes a PO file under `dir` for every MSBT in the archive, keeping its folders
+pub fn po_tree_from_sarc(archive: &Sarc, dir: &Path, profile: &dyn GameProfile) -> Result<Vec<PathBuf>> {
+    write_po_tree(pos_from_sarc(archive, profile)?, dir)
+}
+
+fn write_po_tree(pos: Vec<(PathBuf, Pot)>, dir: &Path) -> Result<Vec<PathBuf>> {
+    pos.into_iter().map(|(name, pot)| {
+        let path = dir.join(name);
+        let write = || -> Result<()> {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            pot.write(&mut File::create(&path)?)?;
+            Ok(())
+        };
+        write().map_err(|e| e.in_file(&path))?;
+        Ok(path)
+    }).collect()
+}
+
+// Every PO file under `dir` with the archive path of its MSBT
+fn po_tree_files(dir: &Path) -> Result<Vec<(PathBuf, String)>> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+        let entry = entry.map_err(|e| Error::from(std::io::Error::from(e)).in_file(dir))?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().and_then(|e| e.to_str()) != Some("po") {
+            continue;
+        }
+        // archive paths always use "/"
+        let name: Vec<String> = path.strip_prefix(dir).unwrap().with_extension("msbt")
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        files.push((path.to_path_buf(), name.join("/")));
+    }
+    Ok(files)
+}
+
+fn build_po_file(path: &Path, profile: &dyn GameProfile) -> Result<(Msbt, Vec<u8>)> {
+    let build = || -> Result<(Msbt, Vec<u8>)> {
+        let msbt = msbt_from_po(&mut std::io::BufReader::new(File::open(path)?), profile)?;
+        let mut bytes = Vec::new();
+        msbt.write_to(&mut bytes)?;
+        Ok((msbt, bytes))
+    };
+    build().map_err(|e| e.in_file(path))
+}
+
+// Builds an MSBT from every PO file under `dir` into a copy of `base`, so files
+// without a PO are kept. Without a base the archive takes the MSBTs' byte order.
+pub fn sarc_from_po_tree(dir: &Path, base: Option<&Sarc>, profile: &dyn GameProfile) -> Result<Sarc> {
+    let mut archive = base.cloned();
+    for (path, name) in po_tree_files(dir)? {
+        let (msbt, bytes) = build_po_file(&path, profile)?;
+        archive.get_or_insert_with(|| Sarc::new(msbt.header().endianness()))
+            .insert(&name, bytes);
+    }
+    Ok(archive.unwrap_or_else(|| Sarc::new(byteordered::Endianness::Big)))
+}
+
+// Whether a build reads the same from both entries: their text and the
+// comments that carry data
+fn same_build_input(a: &PotMessage, b: &PotMessage) -> bool {
+    let data_comments = |m: &PotMessage| -> Vec<String> {
+        m.extracted_comments.iter()
+            .filter(|c| [attributes::COMMENT_PREFIX, plurals::COMMENT_PREFIX, plurals::SOURCE_COMMENT_PREFIX, VALUE_PREFIX]
+                .iter().any(|prefix| c.starts_with(prefix)))
+            .cloned()
+            .collect()
+    };
+    a.context == b.context && a.id == b.id && a.id_plural == b.id_plural && a.strings == b.strings
+        && data_comments(a) == data_comments(b)
+}
+
+// Whether the PO file holds what extracting `original` gives, so building it
+// is only a round trip, which isn't byte for byte for every MSBT
+fn po_matches_msbt(path: &Path, original: &[u8], profile: &dyn GameProfile) -> Result<bool> {
+    let pot = Pot::read(&mut std::io::BufReader::new(File::open(path).map_err(|e| Error::from(e).in_file(path))?));
+    let fresh = po_from_msbt(&mut Cursor::new(original), profile).map_err(|e| e.in_file(path))?;
+    fn entries(pot: &Pot) -> Vec<&PotMessage> {
+        pot.messages.iter().filter(|m| !locale::is_header(m) && !m.obsolete).collect()
+    }
+    let (entries, fresh) = (entries(&pot), entries(&fresh));
+    Ok(entries.len() == fresh.len() && entries.iter().zip(&fresh).all(|(a, b)| same_build_input(a, b)))
+}
+
+// The Yaz0-compressed message archive inside a Bootup_<region>.pack
+pub fn message_archive_name(locale: &Locale) -> String {
+    format!("Message/Msg_{}.product.ssarc", locale.region)
+}
+
+fn message_archive(pack: &Sarc, locale: &Locale) -> Result<(String, Sarc)> {
+    let name = message_archive_name(locale);
+    let file = pack.get(&name).ok_or_else(|| Error::Sarc(format!("no {} in the pack", name)))?;
+    let archive = Sarc::from_bytes(&file.data).map_err(|e| e.in_file(&name))?;
+    Ok((name, archive))
+}
+
+// Writes the messages of a Bootup_<region>.pack as a PO tree under `dir`, one
+// folder per message group as in Msg_<region>.product.ssarc
+pub fn po_tree_from_pack(pack: &Sarc, locale: &Locale, dir: &Path, profile: &dyn GameProfile) -> Result<Vec<PathBuf>> {
+    let (_, archive) = message_archive(pack, locale)?;
+    let mut pos = pos_from_sarc(&archive, profile)?;
+    for (_, pot) in &mut pos {
+        locale::set_locale(pot, locale);
+    }
+    write_po_tree(pos, dir)
+}
+
+// Rebuilds a Bootup_<region>.pack from the PO tree under `dir`. MSBTs whose PO
+// holds what extracting them gives keep their original bytes; the rest are
+// built and replaced, and their archive paths are returned. With none changed
+// the pack comes back as it was, compressed data included.
+pub fn pack_from_po_tree(dir: &Path, pack: &Sarc, locale: &Locale, level: u32, profile: &dyn GameProfile) -> Result<(Sarc, Vec<String>)> {
+    let (name, mut archive) = message_archive(pack, locale)?;
+    let mut changed = Vec::new();
+    for (path, file_name) in po_tree_files(dir)? {
+        if let Some(original) = archive.get(&file_name) {
+            if po_matches_msbt(&path, &original.data, profile)? {
+                continue;
+            }
+        }
+        let (_, bytes) = build_po_file(&path, profile)?;
+        if archive.get(&file_name).map(|f| &f.data) != Some(&bytes) {
+            changed.push(file_name.clone());
+            archive.insert(&file_name, bytes);
+        }
+    }
+    let mut pack = pack.clone();
+    if !changed.is_empty() {
+        let alignment = yaz0::alignment(&pack.get(&name).unwrap().data);
+        pack.insert(&name, yaz0::compress_aligned(&archive.to_bytes(), level, alignment));
+    }
+    Ok((pack, changed))
+}
+
+// Resource size table entries needed by a pack from pack_from_po_tree: the pack,
+// its message archive and each changed MSBT, under the names the table uses
+pub fn rstb_entries_for_pack(pack: &Sarc, locale: &Locale, changed: &[String]) -> Result<Vec<(String, u32)>> {
+    let (name, archive) = message_archive(pack, locale)?;
+    let pack_name = format!("Pack/Bootup_{}.pack", locale.region);
+    let mut entries = vec![
+        (pack_name.clone(), rstb::resource_size(&pack_name, &pack.to_bytes(), pack.endianness)?),
+        (rstb::resource_name(&name), rstb::resource_size(&name, &pack.get(&name).unwrap().data, pack.endianness)?)
+    ];
+    for file in changed.iter().filter_map(|name| archive.get(name)) {
+        entries.push((file.name.clone(), rstb::resource_size(&file.name, &file.data, archive.endianness)?));
+    }
+    Ok(entries)
 }
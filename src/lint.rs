@@ -0,0 +1,72 @@
+use crate::helper;
+use crate::locale;
+use potty::{Pot, PotMessage};
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub label: String,
+    pub reason: String
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "msgid \"{}\": {}", self.label, self.reason)
+    }
+}
+
+fn problem(message: &PotMessage, reason: String) -> Problem {
+    let label = message.context.as_ref().or(message.id.as_ref()).cloned().unwrap_or_default();
+    Problem { label, reason }
+}
+
+// Tags in `b` but not `a`, with repeats
+fn extra_tags(a: &[String], b: &[String]) -> Vec<String> {
+    let mut a = a.to_vec();
+    b.iter().filter(|name| match a.iter().position(|n| n == *name) {
+        Some(i) => {
+            a.remove(i);
+            false
+        },
+        None => true
+    }).map(|name| format!("[{}]", name)).collect()
+}
+
+// What a build lets through but a reviewer should see: fuzzy and untranslated
+// bilingual entries, and translations whose tags differ from the source text
+pub fn lint(pot: &Pot) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for message in pot.messages.iter().filter(|m| !m.obsolete && !locale::is_header(m)) {
+        if message.flags.iter().any(|f| f == "fuzzy") {
+            problems.push(problem(message, "fuzzy".to_string()));
+        }
+        let (label, source) = match (message.context.as_ref(), message.id.as_ref()) {
+            (Some(label), Some(source)) if label != source => (label, source),
+            _ => continue
+        };
+        if message.strings.iter().all(String::is_empty) {
+            problems.push(problem(message, "untranslated".to_string()));
+            continue;
+        }
+        let source_tags = helper::tag_names(source);
+        for (i, text) in message.strings.iter().enumerate().filter(|(_, t)| !t.is_empty()) {
+            let tags = helper::tag_names(text);
+            let (missing, extra) = (extra_tags(&tags, &source_tags), extra_tags(&source_tags, &tags));
+            if missing.is_empty() && extra.is_empty() {
+                continue;
+            }
+            let mut reason = String::from("tags differ from the source");
+            if message.strings.len() > 1 {
+                reason.push_str(&format!(" in msgstr[{}]", i));
+            }
+            if !missing.is_empty() {
+                reason.push_str(&format!(", missing {}", missing.join(" ")));
+            }
+            if !extra.is_empty() {
+                reason.push_str(&format!(", extra {}", extra.join(" ")));
+            }
+            problems.push(Problem { label: label.clone(), reason });
+        }
+    }
+    problems
+}
@@ -0,0 +1,216 @@
+use crate::error::{Error, Result};
+use potty::{Pot, PotMessage};
+use std::path::Path;
+
+const ONE_FORM: &str = "nplurals=1; plural=0;";
+const TWO_FORMS: &str = "nplurals=2; plural=(n != 1);";
+const TWO_FORMS_FRENCH: &str = "nplurals=2; plural=(n > 1);";
+const THREE_FORMS_RUSSIAN: &str = "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);";
+
+// A game language, by the region code the data directories use ("EUfr") and
+// its BCP 47 tag ("fr-FR")
+#[derive(Debug)]
+pub struct Locale {
+    pub region: &'static str,
+    pub tag: &'static str,
+    pub plural_forms: &'static str
+}
+
+pub const LOCALES: &[Locale] = &[
+    Locale { region: "USen", tag: "en-US", plural_forms: TWO_FORMS },
+    Locale { region: "USes", tag: "es-419", plural_forms: TWO_FORMS },
+    Locale { region: "USfr", tag: "fr-CA", plural_forms: TWO_FORMS_FRENCH },
+    Locale { region: "EUen", tag: "en-GB", plural_forms: TWO_FORMS },
+    Locale { region: "EUde", tag: "de-DE", plural_forms: TWO_FORMS },
+    Locale { region: "EUes", tag: "es-ES", plural_forms: TWO_FORMS },
+    Locale { region: "EUfr", tag: "fr-FR", plural_forms: TWO_FORMS_FRENCH },
+    Locale { region: "EUit", tag: "it-IT", plural_forms: TWO_FORMS },
+    Locale { region: "EUnl", tag: "nl-NL", plural_forms: TWO_FORMS },
+    Locale { region: "EUru", tag: "ru-RU", plural_forms: THREE_FORMS_RUSSIAN },
+    Locale { region: "JPja", tag: "ja-JP", plural_forms: ONE_FORM },
+    Locale { region: "CNzh", tag: "zh-Hans", plural_forms: ONE_FORM },
+    Locale { region: "TWzh", tag: "zh-Hant", plural_forms: ONE_FORM },
+    Locale { region: "KRko", tag: "ko-KR", plural_forms: ONE_FORM }
+];
+
+impl Locale {
+    // A region code or a BCP 47 tag; a bare language like "fr" gets its EU
+    // locale if there is one
+    pub fn find(code: &str) -> Option<&'static Self> {
+        let code = code.replace('_', "-");
+        let language = |l: &&Locale| l.tag.split('-').next().unwrap().eq_ignore_ascii_case(&code);
+        LOCALES.iter()
+            .find(|l| l.region.eq_ignore_ascii_case(&code) || l.tag.eq_ignore_ascii_case(&code))
+            .or_else(|| LOCALES.iter().filter(language).find(|l| l.region.starts_with("EU")))
+            .or_else(|| LOCALES.iter().find(language))
+    }
+
+    // The last region code in a path like "Bootup_EUfr.pack/Message/Msg_EUfr.product.ssarc"
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<&'static Self> {
+        path.as_ref().iter().rev()
+            .filter_map(|c| c.to_str())
+            .flat_map(|c| c.rsplit(|ch: char| !ch.is_ascii_alphanumeric()))
+            .find_map(|word| LOCALES.iter().find(|l| l.region == word))
+    }
+
+    pub fn plural_count(&self) -> usize {
+        plural_count(self.plural_forms).unwrap()
+    }
+}
+
+fn plural_count(forms: &str) -> Option<usize> {
+    forms.split(';')
+        .find_map(|f| f.trim().strip_prefix("nplurals="))
+        .and_then(|n| n.trim().parse().ok())
+}
+
+pub fn is_header(message: &PotMessage) -> bool {
+    message.id.as_deref() == Some("") && message.context.is_none()
+}
+
+pub fn new_header(project: &str) -> PotMessage {
+    let mut header = PotMessage::new();
+    header.id = Some("".to_string());
+    header.strings.push(format!(
+        "Project-Id-Version: {}\nMIME-Version: 1.0\nContent-Type: text/plain; charset=UTF-8\nContent-Transfer-Encoding: 8bit\n",
+        project));
+    header
+}
+
+pub fn header_fields(messages: &[PotMessage]) -> Vec<(String, String)> {
+    messages.iter()
+        .find(|m| is_header(m))
+        .and_then(|m| m.strings.first())
+        .map_or(Vec::new(), |s| s.lines()
+            .filter_map(|l| l.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect())
+}
+
+// Replaces or adds a header line, adding the header if there isn't one
+pub fn set_header_field(pot: &mut Pot, name: &str, value: &str) {
+    let index = match pot.messages.iter().position(is_header) {
+        Some(index) => index,
+        _ => {
+            pot.messages.insert(0, new_header(""));
+            0
+        }
+    };
+    let header = &mut pot.messages[index];
+    let mut lines: Vec<String> = header.strings.first().map_or(Vec::new(), |s| s.lines().map(str::to_string).collect());
+    let line = format!("{}: {}", name, value);
+    match lines.iter().position(|l| matches!(l.split_once(':'), Some((n, _)) if n.trim() == name)) {
+        Some(i) => lines[i] = line,
+        None => lines.push(line)
+    }
+    header.strings = vec![lines.iter().map(|l| format!("{}\n", l)).collect()];
+}
+
+pub fn set_locale(pot: &mut Pot, locale: &Locale) {
+    set_header_field(pot, "Language", locale.tag);
+    set_header_field(pot, "Plural-Forms", locale.plural_forms);
+}
+
+// Header lines we read back have to make sense: UTF-8 text, the plural forms
+// of the language if it's one of the game's, and as many msgstr in every
+// plural entry as Plural-Forms says. Other languages are left to the file,
+// since mods add them.
+pub fn validate(messages: &[PotMessage]) -> Result<()> {
+    let fields = header_fields(messages);
+    let field = |name: &str| fields.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    if let Some(content_type) = field("Content-Type") {
+        let charset = content_type.split(';')
+            .find_map(|p| p.trim().strip_prefix("charset="))
+            .unwrap_or_default();
+        if !charset.eq_ignore_ascii_case("UTF-8") {
+            return Err(Error::Po(format!("header Content-Type: expected charset=UTF-8, found \"{}\"", content_type)));
+        }
+    }
+    let locale = field("Language").and_then(Locale::find);
+    let count = match field("Plural-Forms") {
+        Some(forms) => {
+            let count = plural_count(forms)
+                .ok_or_else(|| Error::Po(format!("header Plural-Forms: expected nplurals, found \"{}\"", forms)))?;
+            if let Some(locale) = locale.filter(|l| l.plural_count() != count) {
+                return Err(Error::Po(format!("header Plural-Forms: {} has {} plural forms, found \"{}\"",
+                    locale.tag, locale.plural_count(), forms)));
+            }
+            Some(count)
+        },
+        None => None
+    };
+    for message in messages.iter().filter(|m| m.id_plural.is_some() && !m.obsolete) {
+        let id = message.context.as_ref().or(message.id.as_ref()).map_or("", String::as_str);
+        match count {
+            Some(count) if message.strings.len() != count =>
+                return Err(Error::Po(format!("expected {} plural forms, found {}", count, message.strings.len())).in_message(id)),
+            None => return Err(Error::Po("plural entry without a Plural-Forms header".to_string()).in_message(id)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(lines: &str) -> PotMessage {
+        let mut header = new_header("botw");
+        header.strings[0].push_str(lines);
+        header
+    }
+
+    fn plural_entry(forms: usize) -> PotMessage {
+        let mut message = PotMessage::new();
+        message.id = Some("Label".to_string());
+        message.id_plural = Some("Label".to_string());
+        message.strings = vec!["x".to_string(); forms];
+        message
+    }
+
+    #[test]
+    fn finds_locales() {
+        assert_eq!(Locale::find("EUfr").unwrap().tag, "fr-FR");
+        assert_eq!(Locale::find("fr_CA").unwrap().region, "USfr");
+        assert_eq!(Locale::find("es-419").unwrap().region, "USes");
+        assert_eq!(Locale::find("fr").unwrap().region, "EUfr");
+        assert_eq!(Locale::find("en").unwrap().region, "EUen");
+        assert_eq!(Locale::find("ja").unwrap().region, "JPja");
+        assert!(Locale::find("pt-BR").is_none());
+        assert_eq!(Locale::from_path("Bootup_EUru.pack/Message/Msg_EUru.product.ssarc").unwrap().tag, "ru-RU");
+    }
+
+    #[test]
+    fn accepts_languages_the_game_lacks() {
+        for language in &["pl", "pt-BR", "pt_BR"] {
+            let forms = "Plural-Forms: nplurals=3; plural=(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);";
+            let messages = vec![header(&format!("Language: {}\n{}\n", language, forms)), plural_entry(3)];
+            assert!(validate(&messages).is_ok(), "{}", language);
+        }
+    }
+
+    #[test]
+    fn checks_plural_forms_of_known_languages() {
+        let messages = [header(&format!("Language: ru-RU\nPlural-Forms: {}\n", THREE_FORMS_RUSSIAN)), plural_entry(3)];
+        assert!(validate(&messages).is_ok());
+        let messages = [header(&format!("Language: ru-RU\nPlural-Forms: {}\n", TWO_FORMS))];
+        assert!(validate(&messages).is_err());
+        let messages = [header("Language: fr-FR\nPlural-Forms: plural=(n > 1);\n")];
+        assert!(validate(&messages).is_err());
+    }
+
+    #[test]
+    fn checks_charset_and_plural_entries() {
+        assert!(validate(&[header("")]).is_ok());
+        let mut latin1 = header("");
+        latin1.strings[0] = latin1.strings[0].replace("UTF-8", "ISO-8859-1");
+        assert!(validate(&[latin1]).is_err());
+        let messages = [header(&format!("Plural-Forms: {}\n", TWO_FORMS)), plural_entry(3)];
+        assert!(validate(&messages).is_err());
+        assert!(validate(&[header(""), plural_entry(2)]).is_err());
+        let mut obsolete = plural_entry(3);
+        obsolete.obsolete = true;
+        assert!(validate(&[header(&format!("Plural-Forms: {}\n", TWO_FORMS)), obsolete]).is_ok());
+    }
+}
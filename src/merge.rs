@@ -0,0 +1,226 @@
+use crate::attributes;
+use crate::locale;
+use crate::{EXTRAS_ID, VERSION_ID, VERSION};
+use potty::{Pot, PotMessage};
+use std::collections::HashMap;
+
+const FUZZY: &str = "fuzzy";
+
+// In entries with the label as msgid, msgstr is the game text in a fresh
+// extraction but may be edited in an existing file, so extraction keeps the
+// game text it came from in an extracted comment, one per msgstr
+const SOURCE_PREFIX: &str = "source: ";
+
+// Bilingual entries are keyed by their msgctxt label, the rest by msgid
+fn label(message: &PotMessage) -> &str {
+    message.context.as_ref().or(message.id.as_ref()).map_or("", String::as_str)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn source_text(message: &PotMessage) -> Option<Vec<String>> {
+    match message.context {
+        // plural entries from plurals::split have a singular and a plural source
+        Some(_) => Some(message.id.iter().chain(message.id_plural.iter()).cloned().collect()),
+        None => {
+            let recorded: Vec<String> = message.extracted_comments.iter()
+                .filter_map(|c| c.strip_prefix(SOURCE_PREFIX))
+                .map(str::to_string)
+                .collect();
+            Some(recorded).filter(|r| !r.is_empty())
+        }
+    }
+}
+
+pub(crate) fn record_source(message: &mut PotMessage) {
+    message.extracted_comments.retain(|c| !c.starts_with(SOURCE_PREFIX));
+    let sources: Vec<String> = message.strings.iter().map(|s| format!("{}{}", SOURCE_PREFIX, escape(s))).collect();
+    message.extracted_comments.extend(sources);
+}
+
+fn mark_fuzzy(message: &mut PotMessage) {
+    if !message.flags.iter().any(|f| f == FUZZY) {
+        message.flags.push(FUZZY.to_string());
+    }
+}
+
+// Carries translator comments, references, flags, attributes and translations
+// over from an older PO file to a fresh extraction. Source text is msgid in
+// bilingual entries and the recorded game text otherwise; when it changed the
+// entry is marked fuzzy. Entries still holding the old game text take the new
+// one instead. Entries written before game text was recorded are compared by
+// their msgstr, so any that differ from the new game text are kept and marked.
+// Labels that are gone become obsolete entries.
+pub fn merge(existing: &Pot, fresh: Pot) -> Pot {
+    let fields = locale::header_fields(&fresh.messages);
+    // older versions kept attributes in the extras, not in comments
+    let edited_attributes = crate::po_version(&existing.messages) == Some(VERSION);
+    let mut old: HashMap<&str, &PotMessage> = existing.messages.iter()
+        .filter(|m| !locale::is_header(m))
+        .map(|m| (label(m), m))
+        .collect();
+    let mut merged = Pot::new();
+    if let Some(header) = existing.messages.iter().find(|m| locale::is_header(m)) {
+        merged.messages.push(header.clone());
+    }
+
+    for mut message in fresh.messages {
+        if locale::is_header(&message) {
+            continue;
+        }
+        let reserved = matches!(message.id.as_deref(), Some(EXTRAS_ID) | Some(VERSION_ID));
+        if message.context.is_none() && !reserved && source_text(&message).is_none() {
+            record_source(&mut message);
+        }
+        if let Some(previous) = old.remove(label(&message)).filter(|_| !reserved) {
+            message.comments = previous.comments.clone();
+            message.references = previous.references.clone();
+            message.flags = previous.flags.clone();
+            if edited_attributes {
+                message.extracted_comments.retain(|c| !c.starts_with(attributes::COMMENT_PREFIX));
+                message.extracted_comments.extend(previous.extracted_comments.iter()
+                    .filter(|c| c.starts_with(attributes::COMMENT_PREFIX))
+                    .cloned());
+            }
+            // a msgstr still holding the game text it came from has nothing to keep
+            let (untouched, changed) = match source_text(previous) {
+                Some(source) => (
+                    previous.context.is_none() && source == previous.strings.iter().map(|s| escape(s)).collect::<Vec<_>>(),
+                    Some(source) != source_text(&message)
+                ),
+                None => {
+                    let same = previous.strings == message.strings;
+                    (same, !same)
+                }
+            };
+            let translated = !untouched
+                && previous.id_plural.is_some() == message.id_plural.is_some()
+                && previous.strings.len() == message.strings.len()
+                && previous.strings.iter().any(|s| !s.is_empty());
+            if previous.obsolete || (changed && !untouched) {
+                mark_fuzzy(&mut message);
+            }
+            if translated {
+                message.strings = previous.strings.clone();
+            }
+        }
+        merged.messages.push(message);
+    }
+
+    for message in existing.messages.iter().filter(|m| !locale::is_header(m) && old.contains_key(label(m))) {
+        let mut message = message.clone();
+        message.obsolete = true;
+        merged.messages.push(message);
+    }
+
+    // the old header keeps lines like Last-Translator, extraction decides the rest
+    for (name, value) in fields {
+        locale::set_header_field(&mut merged, &name, &value);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(context: Option<&str>, id: &str, text: &str) -> PotMessage {
+        let mut message = PotMessage::new();
+        message.context = context.map(str::to_string);
+        message.id = Some(id.to_string());
+        message.strings.push(text.to_string());
+        message
+    }
+
+    fn pot(messages: Vec<PotMessage>) -> Pot {
+        let mut pot = Pot::new();
+        pot.messages = messages;
+        pot
+    }
+
+    fn find<'a>(pot: &'a Pot, label: &str) -> &'a PotMessage {
+        pot.messages.iter().find(|m| super::label(m) == label).unwrap()
+    }
+
+    fn fuzzy(message: &PotMessage) -> bool {
+        message.flags.iter().any(|f| f == FUZZY)
+    }
+
+    #[test]
+    fn first_merge_compares_msgstr_with_game_text() {
+        // committed files have no recorded game text
+        let existing = pot(vec![
+            entry(None, "modded", "Modded"),
+            entry(None, "same", "Game text"),
+            entry(None, "updated", "Old game\ntext"),
+            entry(None, "gone", "Gone")
+        ]);
+        let fresh = pot(vec![
+            entry(None, "modded", "Game text"),
+            entry(None, "same", "Game text"),
+            entry(None, "updated", "New game\ntext")
+        ]);
+        let merged = merge(&existing, fresh);
+        assert_eq!(find(&merged, "modded").strings, vec!["Modded"]);
+        assert!(fuzzy(find(&merged, "modded")));
+        assert_eq!(find(&merged, "same").strings, vec!["Game text"]);
+        assert!(!fuzzy(find(&merged, "same")));
+        assert_eq!(find(&merged, "updated").strings, vec!["Old game\ntext"]);
+        assert!(fuzzy(find(&merged, "updated")));
+        assert_eq!(find(&merged, "updated").extracted_comments, vec!["source: New game\\ntext"]);
+        assert!(find(&merged, "gone").obsolete);
+    }
+
+    #[test]
+    fn marks_entries_whose_recorded_game_text_changed() {
+        let mut modded = entry(None, "modded", "Modded");
+        modded.extracted_comments.push("source: Game 1".to_string());
+        let mut kept = entry(None, "kept", "Modded too");
+        kept.extracted_comments.push("source: Game 2".to_string());
+        kept.comments.push("checked".to_string());
+        let mut untouched = entry(None, "untouched", "Game 3");
+        untouched.extracted_comments.push("source: Game 3".to_string());
+        let existing = pot(vec![modded, kept, untouched, entry(Some("bilingual"), "Hello", "Bonjour")]);
+        let fresh = pot(vec![
+            entry(None, "modded", "Game 1, updated"),
+            entry(None, "kept", "Game 2"),
+            entry(None, "untouched", "Game 3, updated"),
+            entry(Some("bilingual"), "Hello there", "Salut")
+        ]);
+        let merged = merge(&existing, fresh);
+
+        let modded = find(&merged, "modded");
+        assert_eq!(modded.strings, vec!["Modded"]);
+        assert!(fuzzy(modded));
+        assert_eq!(modded.extracted_comments, vec!["source: Game 1, updated"]);
+        let kept = find(&merged, "kept");
+        assert_eq!(kept.strings, vec!["Modded too"]);
+        assert_eq!(kept.comments, vec!["checked"]);
+        assert!(!fuzzy(kept));
+        // still the old game text, so it takes the new one
+        let untouched = find(&merged, "untouched");
+        assert_eq!(untouched.strings, vec!["Game 3, updated"]);
+        assert!(!fuzzy(untouched));
+        let bilingual = find(&merged, "bilingual");
+        assert_eq!(bilingual.strings, vec!["Bonjour"]);
+        assert!(fuzzy(bilingual));
+    }
+
+    #[test]
+    fn keeps_edited_attributes_of_current_files() {
+        let mut speaker = entry(None, "line", "Text");
+        speaker.extracted_comments.push("attribute: Npc_Edited".to_string());
+        let version = |v: u32| entry(None, VERSION_ID, &v.to_string());
+        let mut fresh_speaker = entry(None, "line", "Text");
+        record_source(&mut fresh_speaker);
+        fresh_speaker.extracted_comments.push("attribute: Npc_Game".to_string());
+
+        let merged = merge(&pot(vec![speaker.clone(), version(VERSION)]), pot(vec![fresh_speaker.clone(), version(VERSION)]));
+        assert_eq!(find(&merged, "line").extracted_comments, vec!["source: Text", "attribute: Npc_Edited"]);
+        // version 1 files kept attributes in the extras, which the fresh ones replace
+        let merged = merge(&pot(vec![speaker, version(1)]), pot(vec![fresh_speaker, version(VERSION)]));
+        assert_eq!(find(&merged, "line").extracted_comments, vec!["source: Text", "attribute: Npc_Game"]);
+    }
+}
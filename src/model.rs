@@ -1,8 +1,143 @@
 use serde::{Serialize, Deserialize};
 use std::collections::BTreeMap;
+use std::fmt::Write;
+use byteordered::Endianness;
+use msbt::Encoding;
+use crate::attributes::{self, Layout};
+use crate::helper::Codec;
 
-#[derive(Debug, Deserialize, Serialize)]
+// TSY1 and NLI1 are keyed by label so that labels can be added, removed or renamed.
+// ATR1 values live on each message, only the section layout is kept here.
+#[derive(Debug)]
 pub struct MsbtInfo {
+    pub group_count: u32,
+    pub endianness: ByteOrder,
+    pub encoding: TextEncoding,
+    pub atr1: Option<Atr1>,
+    pub ato1: Option<Vec<u8>>,
+    pub tsy1: Option<BTreeMap<String, u32>>,
+    pub nli1: Option<BTreeMap<String, u32>>,
+}
+
+#[derive(Debug)]
+pub enum Atr1 {
+    Attributes(Layout),
+    // layouts we can't decode are kept as the raw section body
+    Bytes(Vec<u8>),
+}
+
+// Text form, one `key value` pair per line:
+//
+//   group_count 19
+//   endianness little|big
+//   encoding utf8|utf16
+//   atr1 strings | atr1 raw <entry size> | atr1 bytes <hex>
+//   ato1 <hex>
+//   tsy1                     (section present)
+//   tsy1 <label> <style>
+//   nli1                     (section present)
+//   nli1 <label> <global id>
+//
+// Blank lines and lines starting with `#` are ignored.
+impl MsbtInfo {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        writeln!(text, "group_count {}", self.group_count).unwrap();
+        writeln!(text, "endianness {}", match self.endianness {
+            ByteOrder::Little => "little",
+            ByteOrder::Big => "big"
+        }).unwrap();
+        writeln!(text, "encoding {}", match self.encoding {
+            TextEncoding::Utf8 => "utf8",
+            TextEncoding::Utf16 => "utf16"
+        }).unwrap();
+        match self.atr1 {
+            Some(Atr1::Attributes(Layout::Strings)) => writeln!(text, "atr1 strings").unwrap(),
+            Some(Atr1::Attributes(Layout::Raw(size))) => writeln!(text, "atr1 raw {}", size).unwrap(),
+            Some(Atr1::Bytes(ref bytes)) => writeln!(text, "atr1 bytes {}", hex::encode_upper(bytes)).unwrap(),
+            None => {}
+        }
+        if let Some(ref bytes) = self.ato1 {
+            writeln!(text, "ato1 {}", hex::encode_upper(bytes)).unwrap();
+        }
+        for (key, map) in [("tsy1", &self.tsy1), ("nli1", &self.nli1)].iter() {
+            if let Some(map) = map {
+                writeln!(text, "{}", key).unwrap();
+                for (label, value) in map.iter() {
+                    writeln!(text, "{} {} {}", key, label, value).unwrap();
+                }
+            }
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut info = MsbtInfo {
+            group_count: 0,
+            endianness: ByteOrder::Little,
+            encoding: TextEncoding::Utf16,
+            atr1: None,
+            ato1: None,
+            tsy1: None,
+            nli1: None,
+        };
+        let mut group_count = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |what: &str| format!("extras line {}: {} in \"{}\"", i + 1, what, line);
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+            match key {
+                "group_count" => group_count = Some(value.parse().map_err(|_| err("expected a number"))?),
+                "endianness" => info.endianness = match value {
+                    "little" => ByteOrder::Little,
+                    "big" => ByteOrder::Big,
+                    _ => return Err(err("expected little or big"))
+                },
+                "encoding" => info.encoding = match value {
+                    "utf8" => TextEncoding::Utf8,
+                    "utf16" => TextEncoding::Utf16,
+                    _ => return Err(err("expected utf8 or utf16"))
+                },
+                "atr1" => {
+                    let mut parts = value.splitn(2, ' ');
+                    info.atr1 = Some(match (parts.next(), parts.next()) {
+                        (Some("strings"), None) => Atr1::Attributes(Layout::Strings),
+                        (Some("raw"), Some(size)) => Atr1::Attributes(Layout::Raw(
+                            size.parse().map_err(|_| err("expected an entry size"))?)),
+                        (Some("bytes"), Some(bytes)) => Atr1::Bytes(
+                            hex::decode(bytes).map_err(|_| err("expected hex bytes"))?),
+                        _ => return Err(err("expected strings, raw or bytes"))
+                    });
+                },
+                "ato1" => info.ato1 = Some(hex::decode(value).map_err(|_| err("expected hex bytes"))?),
+                "tsy1" | "nli1" => {
+                    let map = if key == "tsy1" { &mut info.tsy1 } else { &mut info.nli1 };
+                    let map = map.get_or_insert_with(BTreeMap::new);
+                    if value.is_empty() {
+                        continue;
+                    }
+                    let mut parts = value.rsplitn(2, ' ');
+                    let number = parts.next().unwrap_or("").parse().map_err(|_| err("expected a number"))?;
+                    let label = parts.next().ok_or_else(|| err("expected a label"))?;
+                    map.insert(label.to_string(), number);
+                },
+                _ => return Err(err("unknown key"))
+            }
+        }
+        info.group_count = group_count.ok_or("extras: missing group_count")?;
+        Ok(info)
+    }
+}
+
+// Layout written by version 1, stored as base64 bincode. It only ever produced
+// little-endian UTF-16, and ties TSY1 and NLI1 to message indices.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct MsbtInfoV1 {
     pub group_count: u32,
     pub atr1: Option<Vec<u8>>,
     pub ato1: Option<Vec<u8>>,
@@ -10,8 +145,166 @@ pub struct MsbtInfo {
     pub nli1: Option<Nli1>,
 }
 
+impl MsbtInfoV1 {
+    // Version 1 files have no attribute comments, so the values in their ATR1
+    // are returned by message index, to be encoded again for the labels.
+    pub fn into_info(self, labels: &[String]) -> (MsbtInfo, Option<Vec<String>>) {
+        let codec = Codec::new(Endianness::Little, Encoding::Utf16);
+        let (atr1, values) = match self.atr1 {
+            Some(bytes) => match (attributes::layout(&bytes, &codec), attributes::decode(&bytes, &codec)) {
+                (Some(layout), Some(values)) => (Some(Atr1::Attributes(layout)), Some(values)),
+                _ => (Some(Atr1::Bytes(bytes)), None)
+            },
+            None => (None, None)
+        };
+        let tsy1 = self.tsy1.map(|bytes| bytes.chunks_exact(4)
+            .zip(labels)
+            .map(|(b, label)| (label.clone(), u32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+            .collect());
+        let nli1 = self.nli1.map(|nli1| nli1.global_ids.iter()
+            .filter_map(|(&id, &index)| labels.get(index as usize).map(|label| (label.clone(), id)))
+            .collect());
+        let info = MsbtInfo {
+            group_count: self.group_count,
+            endianness: ByteOrder::Little,
+            encoding: TextEncoding::Utf16,
+            atr1,
+            ato1: self.ato1,
+            tsy1,
+            nli1,
+        };
+        (info, values)
+    }
+}
+
+// Maps global message ids to message indices
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Nli1 {
   pub id_count: u32,
   pub global_ids: BTreeMap<u32, u32>,
 }
+
+#[derive(Debug, Clone, Copy)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl From<Endianness> for ByteOrder {
+    fn from(endianness: Endianness) -> Self {
+        match endianness {
+            Endianness::Little => Self::Little,
+            Endianness::Big => Self::Big,
+        }
+    }
+}
+
+impl From<ByteOrder> for Endianness {
+    fn from(order: ByteOrder) -> Self {
+        match order {
+            ByteOrder::Little => Self::Little,
+            ByteOrder::Big => Self::Big,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16,
+}
+
+impl From<Encoding> for TextEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Utf8 => Self::Utf8,
+            Encoding::Utf16 => Self::Utf16,
+        }
+    }
+}
+
+impl From<TextEncoding> for Encoding {
+    fn from(encoding: TextEncoding) -> Self {
+        match encoding {
+            TextEncoding::Utf8 => Self::Utf8,
+            TextEncoding::Utf16 => Self::Utf16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels() -> Vec<String> {
+        vec!["Talk00".to_string(), "Talk01".to_string(), "Talk02".to_string()]
+    }
+
+    // base64 bincode, as version 1 wrote it
+    fn v1_round_trip(info: &MsbtInfoV1) -> MsbtInfoV1 {
+        let text = base64::encode(bincode::serialize(info).unwrap());
+        bincode::deserialize(&base64::decode(&text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn migrates_version_1() {
+        let codec = Codec::new(Endianness::Little, Encoding::Utf16);
+        let speakers = vec!["Npc_Zora006".to_string(), "".to_string(), "Npc_Zora006".to_string()];
+        let v1 = v1_round_trip(&MsbtInfoV1 {
+            group_count: 101,
+            atr1: Some(attributes::encode(&Layout::Strings, &speakers, &codec).unwrap()),
+            ato1: None,
+            tsy1: Some([3u32, 0, 7].iter().flat_map(|s| s.to_le_bytes().to_vec()).collect()),
+            nli1: Some(Nli1 { id_count: 1, global_ids: vec![(50, 2)].into_iter().collect() })
+        });
+        let (info, values) = v1.into_info(&labels());
+        assert_eq!(info.group_count, 101);
+        assert!(matches!(info.endianness, ByteOrder::Little));
+        assert!(matches!(info.encoding, TextEncoding::Utf16));
+        assert!(matches!(info.atr1, Some(Atr1::Attributes(Layout::Strings))));
+        assert_eq!(values, Some(speakers));
+        let tsy1 = info.tsy1.unwrap();
+        assert_eq!((tsy1["Talk00"], tsy1["Talk01"], tsy1["Talk02"]), (3, 0, 7));
+        assert_eq!(info.nli1.unwrap().into_iter().collect::<Vec<_>>(), vec![("Talk02".to_string(), 50)]);
+    }
+
+    #[test]
+    fn keeps_version_1_atr1_it_cannot_decode() {
+        let bytes = vec![3, 0, 0, 0, 2, 0, 0, 0, 0xAB];
+        let v1 = MsbtInfoV1 { group_count: 1, atr1: Some(bytes.clone()), ato1: None, tsy1: None, nli1: None };
+        let (info, values) = v1.into_info(&labels());
+        assert!(matches!(info.atr1, Some(Atr1::Bytes(ref b)) if b == &bytes));
+        assert!(values.is_none());
+    }
+
+    #[test]
+    fn round_trips_text() {
+        let info = MsbtInfo {
+            group_count: 19,
+            endianness: ByteOrder::Big,
+            encoding: TextEncoding::Utf8,
+            atr1: Some(Atr1::Attributes(Layout::Raw(2))),
+            ato1: Some(vec![0xDE, 0xAD]),
+            tsy1: Some(vec![("Label with spaces".to_string(), 4)].into_iter().collect()),
+            nli1: Some(BTreeMap::new())
+        };
+        let text = info.to_text();
+        assert_eq!(text, "group_count 19\nendianness big\nencoding utf8\natr1 raw 2\nato1 DEAD\n\
+            tsy1\ntsy1 Label with spaces 4\nnli1\n");
+        let read = MsbtInfo::from_text(&format!("# comment\n\n{}", text)).unwrap();
+        assert_eq!(read.to_text(), text);
+        assert_eq!(read.tsy1.unwrap()["Label with spaces"], 4);
+        assert!(read.nli1.unwrap().is_empty());
+
+        let bytes = MsbtInfo { atr1: Some(Atr1::Bytes(vec![1, 2])), ato1: None, tsy1: None, nli1: None, ..info };
+        assert_eq!(MsbtInfo::from_text(&bytes.to_text()).unwrap().to_text(), bytes.to_text());
+    }
+
+    #[test]
+    fn rejects_bad_text() {
+        assert!(MsbtInfo::from_text("endianness big\n").is_err());
+        for line in &["group_count many", "endianness middle", "encoding latin1", "atr1 raw", "atr1 bytes XY", "tsy1 Label", "colour red"] {
+            assert!(MsbtInfo::from_text(&format!("group_count 1\n{}\n", line)).is_err(), "{}", line);
+        }
+    }
+}
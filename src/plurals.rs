@@ -0,0 +1,275 @@
+use crate::error::{Error, Result};
+use crate::helper::{self, Param, Value};
+use crate::locale::{self, Locale};
+use crate::merge;
+use crate::profile::GameProfile;
+use potty::{Pot, PotMessage};
+
+// "plural: <prefix bytes> <suffix bytes> [SPSwitch <forms the locale doesn't use>]"
+pub const COMMENT_PREFIX: &str = "plural: ";
+// The same for a bilingual msgid, whose first two forms become msgid and msgid_plural
+pub const SOURCE_COMMENT_PREFIX: &str = "plural source: ";
+const TAG: &str = "SPSwitch";
+
+// Splits a bilingual msgid with exactly one SPSwitch into its first two forms,
+// so msgid and msgid_plural are the singular and plural source text
+fn split_source(message: &mut PotMessage, names: &[String]) {
+    let id = message.id.clone().unwrap_or_default();
+    let spans = helper::find_tags(&id, TAG);
+    let span = match spans.as_slice() {
+        [span] if message.context.is_some() && names.len() >= 2 => span,
+        _ => {
+            message.id_plural = message.id.clone();
+            return;
+        }
+    };
+    let (prefix, suffix) = (&id[..span.start], &id[span.end..]);
+    let form = |name: &str| span.params.iter()
+        .find(|(n, _)| n == name)
+        .map_or("", |(_, v)| v.as_str());
+    let unused: Vec<Param> = span.params.iter()
+        .filter(|(n, _)| !names[..2].contains(n))
+        .map(|(n, v)| Param::new(n, Value::String(v.clone())))
+        .collect();
+    message.id = Some(format!("{}{}{}", prefix, helper::escape_text(form(&names[0])), suffix));
+    message.id_plural = Some(format!("{}{}{}", prefix, helper::escape_text(form(&names[1])), suffix));
+    message.extracted_comments.push(format!("{}{} {} {}",
+        SOURCE_COMMENT_PREFIX, prefix.len(), suffix.len(), helper::format_tag(TAG, &unused)));
+}
+
+// Puts the SPSwitch split_source took apart back into msgid
+fn fold_source(folded: &mut PotMessage, message: &PotMessage, profile: &dyn GameProfile) -> Result<()> {
+    let comment = match message.extracted_comments.iter().find_map(|c| c.strip_prefix(SOURCE_COMMENT_PREFIX)) {
+        Some(comment) => comment,
+        None => return Ok(())
+    };
+    let mut parts = comment.splitn(3, ' ');
+    let (prefix, suffix): (usize, usize) = match (parts.next().and_then(|n| n.parse().ok()), parts.next().and_then(|n| n.parse().ok())) {
+        (Some(prefix), Some(suffix)) => (prefix, suffix),
+        _ => return Err(Error::Po(format!("bad \"{}\" comment", SOURCE_COMMENT_PREFIX.trim_end())))
+    };
+    let unused = parts.next()
+        .and_then(|tag| helper::find_tags(tag, TAG).into_iter().next())
+        .map(|span| span.params)
+        .unwrap_or_default();
+    let id = message.id.clone().unwrap_or_default();
+    let id_plural = message.id_plural.clone().unwrap_or_default();
+    let mut forms = Vec::new();
+    let mismatch = || Error::Po("msgid and msgid_plural don't match their plural comment".to_string());
+    for s in &[&id, &id_plural] {
+        let form = s.len().checked_sub(suffix)
+            .filter(|&end| end >= prefix)
+            .and_then(|end| s.get(prefix..end))
+            .ok_or_else(mismatch)?;
+        forms.push(helper::unescape_text(form));
+    }
+    let params: Vec<Param> = profile.new_params(TAG).into_iter().enumerate().map(|(i, p)| {
+        let value = forms.get(i).cloned()
+            .or_else(|| unused.iter().find(|(n, _)| n == &p.name).map(|(_, v)| v.clone()))
+            .unwrap_or_default();
+        Param::new(&p.name, Value::String(value))
+    }).collect();
+    folded.id = Some(format!("{}{}{}", &id[..prefix], helper::format_tag(TAG, &params), &id[id.len() - suffix..]));
+    folded.extracted_comments.retain(|c| !c.starts_with(SOURCE_COMMENT_PREFIX));
+    Ok(())
+}
+
+// Messages with exactly one SPSwitch become msgid_plural entries with a msgstr
+// per plural form of the locale, the text around the tag repeated in each.
+pub fn split(pot: &mut Pot, profile: &dyn GameProfile, locale: &Locale) {
+    let names: Vec<String> = profile.new_params(TAG).into_iter().map(|p| p.name).collect();
+    let count = locale.plural_count().min(names.len());
+    for message in pot.messages.iter_mut().filter(|m| !locale::is_header(m) && m.id_plural.is_none()) {
+        let text = match message.strings.first() {
+            Some(text) => text.clone(),
+            _ => continue
+        };
+        let spans = helper::find_tags(&text, TAG);
+        let span = match spans.as_slice() {
+            [span] => span,
+            _ => continue
+        };
+        let (prefix, suffix) = (&text[..span.start], &text[span.end..]);
+        let form = |name: &str| span.params.iter()
+            .find(|(n, _)| n == name)
+            .map_or("", |(_, v)| v.as_str());
+        let unused: Vec<Param> = span.params.iter()
+            .filter(|(n, _)| !names[..count].contains(n))
+            .map(|(n, v)| Param::new(n, Value::String(v.clone())))
+            .collect();
+        message.strings = names[..count].iter()
+            .map(|name| format!("{}{}{}", prefix, helper::escape_text(form(name)), suffix))
+            .collect();
+        split_source(message, &names);
+        if message.context.is_none() {
+            merge::record_source(message);
+        }
+        message.extracted_comments.push(format!("{}{} {} {}",
+            COMMENT_PREFIX, prefix.len(), suffix.len(), helper::format_tag(TAG, &unused)));
+    }
+    locale::set_locale(pot, locale);
+}
+
+fn common_prefix(a: &str, b: &str) -> usize {
+    a.char_indices().zip(b.chars())
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i)
+}
+
+fn common_suffix(a: &str, b: &str) -> usize {
+    a.char_indices().rev().zip(b.chars().rev())
+        .find(|((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, x), _)| a.len() - i - x.len_utf8())
+}
+
+// The text around the tag, as split wrote it if translators kept it the same
+// in every form, otherwise whatever the forms have in common
+fn affixes(strings: &[String], stored: Option<(usize, usize)>) -> (usize, usize) {
+    let first = &strings[0];
+    if let Some((prefix, suffix)) = stored {
+        let fits = strings.iter().all(|s| s.len() >= prefix + suffix
+            && s.is_char_boundary(prefix) && s.is_char_boundary(s.len() - suffix)
+            && s[..prefix] == first[..prefix] && s[s.len() - suffix..] == first[first.len() - suffix..]);
+        if fits {
+            return (prefix, suffix);
+        }
+    }
+    let prefix = strings.iter().map(|s| common_prefix(first, s)).min().unwrap_or(0);
+    let suffix = strings.iter().map(|s| common_suffix(&first[prefix..], &s[prefix..])).min().unwrap_or(0);
+    (prefix, suffix)
+}
+
+// Turns a msgid_plural entry back into a single msgstr with an SPSwitch tag
+pub fn fold(message: &PotMessage, profile: &dyn GameProfile) -> Result<PotMessage> {
+    let mut folded = message.clone();
+    if message.id_plural.is_none() {
+        return Ok(folded);
+    }
+    folded.id_plural = None;
+    folded.extracted_comments.retain(|c| !c.starts_with(COMMENT_PREFIX));
+    fold_source(&mut folded, message, profile)?;
+    if message.strings.iter().all(String::is_empty) {
+        folded.strings = vec![String::new()];
+        return Ok(folded);
+    }
+
+    let comment = message.extracted_comments.iter()
+        .find_map(|c| c.strip_prefix(COMMENT_PREFIX))
+        .unwrap_or_default();
+    let mut parts = comment.splitn(3, ' ');
+    let stored = match (parts.next().and_then(|n| n.parse().ok()), parts.next().and_then(|n| n.parse().ok())) {
+        (Some(prefix), Some(suffix)) => Some((prefix, suffix)),
+        _ => None
+    };
+    let unused = parts.next()
+        .and_then(|tag| helper::find_tags(tag, TAG).into_iter().next())
+        .map(|span| span.params)
+        .unwrap_or_default();
+
+    let (prefix, suffix) = affixes(&message.strings, stored);
+    let mut forms = Vec::new();
+    for s in &message.strings {
+        let form = &s[prefix..s.len() - suffix];
+        if form.replace("[[", "").contains('[') {
+            return Err(Error::Po(format!("plural forms can only differ in text, found \"{}\"", form)));
+        }
+        forms.push(helper::unescape_text(form));
+    }
+    let params: Vec<Param> = profile.new_params(TAG).into_iter().enumerate().map(|(i, p)| {
+        let value = forms.get(i).cloned()
+            .or_else(|| unused.iter().find(|(n, _)| n == &p.name).map(|(_, v)| v.clone()))
+            .unwrap_or_default();
+        Param::new(&p.name, Value::String(value))
+    }).collect();
+    let first = &message.strings[0];
+    folded.strings = vec![format!("{}{}{}", &first[..prefix], helper::format_tag(TAG, &params), &first[first.len() - suffix..])];
+    Ok(folded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games;
+
+    fn pot(entries: &[(Option<&str>, &str, &str)]) -> Pot {
+        let mut pot = Pot::new();
+        for &(context, id, text) in entries {
+            let mut message = PotMessage::new();
+            message.context = context.map(str::to_string);
+            message.id = Some(id.to_string());
+            message.strings.push(text.to_string());
+            pot.messages.push(message);
+        }
+        pot
+    }
+
+    const ENTRIES: &[(Option<&str>, &str, &str)] = &[
+        (None, "Plural", r#"You got [Number]x[SPSwitch singular=" ruby" plural=" rubies" plural2=" rubies"]. [[ok]"#),
+        (None, "Plain", "No tag [[here]"),
+        (None, "Two", r#"[SPSwitch singular="a"][SPSwitch singular="b"]"#)
+    ];
+
+    #[test]
+    fn splits_and_folds_each_locale() {
+        let profile = games::botw().unwrap();
+        let original = pot(ENTRIES);
+        for &(code, forms) in &[("EUde", 2), ("JPja", 1), ("EUru", 3)] {
+            let mut split_pot = pot(ENTRIES);
+            split(&mut split_pot, &profile, Locale::find(code).unwrap());
+            locale::validate(&split_pot.messages).unwrap();
+            let plural = split_pot.messages.iter().find(|m| m.id.as_deref() == Some("Plural")).unwrap();
+            assert_eq!(plural.strings.len(), forms, "{}", code);
+            assert_eq!(plural.strings[0], "You got [Number]x ruby. [[ok]");
+            assert_eq!(plural.extracted_comments[0], "source: You got [Number]x ruby. [[ok]");
+            for message in split_pot.messages.iter().filter(|m| !locale::is_header(m)) {
+                let folded = fold(message, &profile).unwrap();
+                let original = original.messages.iter().find(|m| m.id == folded.id).unwrap();
+                assert_eq!(folded.strings, original.strings, "{}", code);
+                assert!(folded.id_plural.is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn folds_forms_translators_reworded() {
+        let profile = games::botw().unwrap();
+        let mut message = PotMessage::new();
+        message.id = Some("Label".to_string());
+        message.id_plural = Some("Label".to_string());
+        message.strings = vec!["Ein Rubin!".to_string(), "Viele Rubine!".to_string()];
+        message.extracted_comments.push(format!("{}0 1 [SPSwitch plural2=\"x\"]", COMMENT_PREFIX));
+        let folded = fold(&message, &profile).unwrap();
+        assert_eq!(folded.strings, vec![r#"[SPSwitch singular="Ein Rubin" plural="Viele Rubine" plural2="x"]!"#]);
+
+        message.strings = vec!["[Number]".to_string(), "[Color name=\"red\"]".to_string()];
+        assert!(fold(&message, &profile).is_err());
+    }
+
+    #[test]
+    fn splits_bilingual_source() {
+        let profile = games::botw().unwrap();
+        let source = r#"[SPSwitch singular="a ruby" plural="rubies"] found"#;
+        let mut bilingual = pot(&[(Some("Label"), source, r#"[SPSwitch singular="un rubis" plural="rubis"] trouvé"#)]);
+        split(&mut bilingual, &profile, Locale::find("EUfr").unwrap());
+        let message = bilingual.messages.iter().find(|m| m.context.is_some()).unwrap();
+        assert_eq!(message.id.as_deref(), Some("a ruby found"));
+        assert_eq!(message.id_plural.as_deref(), Some("rubies found"));
+        let folded = fold(message, &profile).unwrap();
+        assert_eq!(folded.id.as_deref(), Some(r#"[SPSwitch singular="a ruby" plural="rubies"] found"#));
+        assert!(!folded.extracted_comments.iter().any(|c| c.starts_with(SOURCE_COMMENT_PREFIX)));
+    }
+
+    #[test]
+    fn rejects_msgid_shorter_than_its_plural_comment() {
+        let profile = games::botw().unwrap();
+        let mut message = PotMessage::new();
+        message.context = Some("Label".to_string());
+        message.id = Some("ab".to_string());
+        message.id_plural = Some("abcdef".to_string());
+        message.strings = vec!["x".to_string(), "y".to_string()];
+        message.extracted_comments.push(format!("{}0 5 [SPSwitch]", SOURCE_COMMENT_PREFIX));
+        assert!(matches!(fold(&message, &profile), Err(Error::Po(_))));
+        message.extracted_comments = vec![format!("{}2 1 [SPSwitch]", SOURCE_COMMENT_PREFIX)];
+        assert!(matches!(fold(&message, &profile), Err(Error::Po(_))));
+    }
+}
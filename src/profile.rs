@@ -0,0 +1,202 @@
+use crate::error::{Error, Result};
+use crate::helper::{self, Codec, Param, Value};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// A game's tags, loaded from TOML or YAML:
+//
+//   name = "botw"
+//
+//   [maps]
+//   colors = { "0" = "red", "65535" = "unset" }
+//
+//   [[tags]]
+//   group = 0x00
+//   code = 0x03
+//   name = "Color"
+//   params = [ { name = "name", type = "u16", map = "colors" } ]
+//
+// Param types are u8, u16, f32, string and bytes; `default` is written when a
+// param is left out of a PO tag and gives bytes params their length. Optional
+// params are only shown when they differ from the default. Tags without a name
+// are shown as "GG:CC" hex codes. Tags with `variants = true` choose between
+// their string params and can have them extracted as entries of their own.
+// Tags with `paired = true` span the text up to a closing [/Name] tag; others
+// stand alone and can't be closed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub maps: BTreeMap<String, BTreeMap<String, String>>,
+    #[serde(default)]
+    pub tags: Vec<TagDef>
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TagDef {
+    pub group: u16,
+    pub code: u16,
+    pub name: Option<String>,
+    #[serde(default)]
+    pub params: Vec<ParamDef>,
+    #[serde(default)]
+    pub variants: bool,
+    #[serde(default)]
+    pub paired: bool
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ParamDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: ParamKind,
+    pub default: Option<String>,
+    #[serde(default)]
+    pub optional: bool,
+    pub map: Option<String>
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamKind {
+    U8,
+    U16,
+    F32,
+    String,
+    Bytes
+}
+
+impl ParamDef {
+    fn to_param(&self, maps: &BTreeMap<String, BTreeMap<String, String>>) -> std::result::Result<Param, String> {
+        let empty = match self.kind {
+            ParamKind::U8 => Value::U8(0),
+            ParamKind::U16 => Value::U16(0),
+            ParamKind::F32 => Value::F32(0.0),
+            ParamKind::String => Value::String("".to_string()),
+            ParamKind::Bytes => Value::Bytes(0, Vec::new())
+        };
+        let value = match self.default {
+            Some(ref default) => empty.parse(default)?,
+            _ => empty
+        };
+        let mut param = Param::new(&self.name, value);
+        if self.optional {
+            param.default = Some(param.value.to_string());
+        }
+        if let Some(ref map) = self.map {
+            let map = maps.get(map).ok_or(format!("unknown map \"{}\"", map))?;
+            param.map = map.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        }
+        Ok(param)
+    }
+}
+
+impl Profile {
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let profile: Self = toml::from_str(s).map_err(|e| Error::Profile(e.to_string()))?;
+        profile.validate()?;
+        Ok(profile)
+    }
+    pub fn from_yaml(s: &str) -> Result<Self> {
+        let profile: Self = serde_yaml::from_str(s).map_err(|e| Error::Profile(e.to_string()))?;
+        profile.validate()?;
+        Ok(profile)
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml") | Some("yml") => Self::from_yaml(&text),
+            _ => Self::from_toml(&text)
+        }.map_err(|e| e.in_file(path))
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (i, tag) in self.tags.iter().enumerate() {
+            let name = self.tag_codes_to_name(tag.group, tag.code);
+            if self.tags[..i].iter().any(|t| (t.group, t.code) == (tag.group, tag.code)) {
+                return Err(Error::Profile(format!("tag {} is defined twice", name)));
+            }
+            if tag.name.is_some() && self.tags[..i].iter().any(|t| t.name == tag.name) {
+                return Err(Error::Profile(format!("tag name \"{}\" is used twice", name)));
+            }
+            for p in &tag.params {
+                p.to_param(&self.maps)
+                    .map_err(|e| Error::Profile(format!("tag {} param \"{}\": {}", name, p.name, e)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn tag(&self, group_code: u16, code: u16) -> Option<&TagDef> {
+        self.tags.iter().find(|t| t.group == group_code && t.code == code)
+    }
+}
+
+// Everything the converters need to know about a game. The text hooks see the
+// text between tags; the defaults only escape `[`, which the PO parser undoes.
+pub trait GameProfile {
+    fn name(&self) -> &str;
+    fn tag_codes_to_name(&self, group_code: u16, code: u16) -> String;
+    fn tag_name_to_codes(&self, name: &str) -> Option<(u16, u16)>;
+    fn new_params(&self, name: &str) -> Vec<Param>;
+    fn variant_tags(&self) -> Vec<String> {
+        Vec::new()
+    }
+    fn paired_tags(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn text_to_po(&self, codec: &Codec, bytes: &[u8]) -> String {
+        helper::escape_text(&codec.decode(bytes))
+    }
+    fn text_to_msbt(&self, codec: &Codec, text: &str) -> Vec<u8> {
+        codec.encode(text)
+    }
+}
+
+impl GameProfile for Profile {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn tag_codes_to_name(&self, group_code: u16, code: u16) -> String {
+        self.tag(group_code, code)
+            .and_then(|t| t.name.clone())
+            .unwrap_or_else(|| format!("{:02X}:{:02X}", group_code, code))
+    }
+
+    fn tag_name_to_codes(&self, name: &str) -> Option<(u16, u16)> {
+        self.tags.iter()
+            .find(|t| t.name.as_deref() == Some(name))
+            .map(|t| (t.group, t.code))
+            .or_else(|| {
+                let mut group_tag = name.split(':');
+                let group_code = u8::from_str_radix(group_tag.next()?, 16).ok()?;
+                let code = u8::from_str_radix(group_tag.next()?, 16).ok()?;
+                Some((group_code as u16, code as u16))
+            })
+    }
+
+    fn new_params(&self, name: &str) -> Vec<Param> {
+        self.tag_name_to_codes(name)
+            .and_then(|(group_code, code)| self.tag(group_code, code))
+            .map(|t| t.params.iter().filter_map(|p| p.to_param(&self.maps).ok()).collect())
+            .unwrap_or_default()
+    }
+
+    fn variant_tags(&self) -> Vec<String> {
+        self.tags.iter()
+            .filter(|t| t.variants)
+            .filter_map(|t| t.name.clone())
+            .collect()
+    }
+
+    fn paired_tags(&self) -> Vec<String> {
+        self.tags.iter()
+            .filter(|t| t.paired)
+            .filter_map(|t| t.name.clone())
+            .collect()
+    }
+}
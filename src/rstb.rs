@@ -0,0 +1,274 @@
+use crate::error::{Error, Result};
+use crate::yaz0;
+use byteordered::{ByteOrdered, Endianness};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+const HEADER_SIZE: usize = 0xC;
+const CRC_ENTRY_SIZE: usize = 8;
+const NAME_ENTRY_SIZE: usize = 132;
+const NAME_SIZE: usize = 128;
+
+fn bad(reason: String) -> Error {
+    Error::Rstb(reason)
+}
+
+fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = (0..8).fold(i as u32, |c, _| if c & 1 == 1 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 });
+    }
+    table
+}
+
+pub fn crc32(name: &str) -> u32 {
+    let table = crc_table();
+    !name.bytes().fold(!0, |c, b| table[((c ^ b as u32) & 0xFF) as usize] ^ (c >> 8))
+}
+
+// The resource size table (ResourceSizeTable.product.srsizetable) BotW uses to
+// reserve memory for each resource. Entries are keyed by the CRC32 of the
+// resource name, except for names whose CRC32 collides, which are kept by name.
+// Wii U tables are big endian and Switch tables little endian.
+#[derive(Debug, Clone)]
+pub struct Rstb {
+    pub endianness: Endianness,
+    pub crc_entries: BTreeMap<u32, u32>,
+    pub name_entries: BTreeMap<String, u32>
+}
+
+fn read_u32(bytes: &[u8], offset: usize, endianness: Endianness) -> u32 {
+    let b = [bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]];
+    match endianness {
+        Endianness::Little => u32::from_le_bytes(b),
+        Endianness::Big => u32::from_be_bytes(b)
+    }
+}
+
+impl Rstb {
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    // A Yaz0-compressed table is decompressed first
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if yaz0::is_compressed(bytes) {
+            return Self::from_bytes(&yaz0::decompress(bytes)?);
+        }
+        if bytes.len() < HEADER_SIZE || &bytes[..4] != b"RSTB" {
+            return Err(bad("expected RSTB at 0x0".to_string()));
+        }
+        // the header doesn't mark the byte order, only one gives the file's size
+        let endianness = [Endianness::Big, Endianness::Little].iter().copied().find(|&e| {
+            let crcs = read_u32(bytes, 4, e) as usize;
+            let names = read_u32(bytes, 8, e) as usize;
+            crcs.checked_mul(CRC_ENTRY_SIZE)
+                .and_then(|c| names.checked_mul(NAME_ENTRY_SIZE).and_then(|n| c.checked_add(n)))
+                == Some(bytes.len() - HEADER_SIZE)
+        }).ok_or_else(|| bad("entry counts don't match the file size".to_string()))?;
+
+        let crcs = read_u32(bytes, 4, endianness) as usize;
+        let names = read_u32(bytes, 8, endianness) as usize;
+        let crc_entries = (0..crcs)
+            .map(|i| HEADER_SIZE + i * CRC_ENTRY_SIZE)
+            .map(|offset| (read_u32(bytes, offset, endianness), read_u32(bytes, offset + 4, endianness)))
+            .collect();
+        let names_offset = HEADER_SIZE + crcs * CRC_ENTRY_SIZE;
+        let name_entries = (0..names)
+            .map(|i| names_offset + i * NAME_ENTRY_SIZE)
+            .map(|offset| {
+                let name = &bytes[offset..offset + NAME_SIZE];
+                let len = name.iter().position(|&c| c == 0).unwrap_or(NAME_SIZE);
+                (String::from_utf8_lossy(&name[..len]).to_string(), read_u32(bytes, offset + NAME_SIZE, endianness))
+            })
+            .collect();
+        Ok(Self{ endianness, crc_entries, name_entries })
+    }
+
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.name_entries.get(name).or_else(|| self.crc_entries.get(&crc32(name))).copied()
+    }
+
+    // Replaces the entry for this name, or adds it by CRC32
+    pub fn set(&mut self, name: &str, size: u32) {
+        match self.name_entries.get_mut(name) {
+            Some(entry) => *entry = size,
+            None => {
+                self.crc_entries.insert(crc32(name), size);
+            }
+        }
+    }
+
+    // Raises an existing entry to `size`, returning the old size if it was smaller.
+    // Missing entries are left out since the game then doesn't check the size.
+    pub fn grow(&mut self, name: &str, size: u32) -> Option<u32> {
+        let old = self.get(name)?;
+        if old >= size {
+            return None;
+        }
+        self.set(name, size);
+        Some(old)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let len = HEADER_SIZE + self.crc_entries.len() * CRC_ENTRY_SIZE + self.name_entries.len() * NAME_ENTRY_SIZE;
+        let mut result = Vec::with_capacity(len);
+        let mut writer = ByteOrdered::runtime(&mut result, self.endianness);
+        writer.write_all(b"RSTB").unwrap();
+        writer.write_u32(self.crc_entries.len() as u32).unwrap();
+        writer.write_u32(self.name_entries.len() as u32).unwrap();
+        for (crc, size) in &self.crc_entries {
+            writer.write_u32(*crc).unwrap();
+            writer.write_u32(*size).unwrap();
+        }
+        for (name, size) in &self.name_entries {
+            let mut padded = name.as_bytes().to_vec();
+            padded.resize(NAME_SIZE, 0);
+            writer.write_all(&padded).unwrap();
+            writer.write_u32(*size).unwrap();
+        }
+        result
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn write_compressed_to<W: Write>(&self, mut writer: W, level: u32) -> Result<()> {
+        writer.write_all(&yaz0::compress(&self.to_bytes(), level))?;
+        Ok(())
+    }
+}
+
+// Tables name compressed resources by their uncompressed extension, so
+// "Message/Msg_EUfr.product.ssarc" is listed as "Message/Msg_EUfr.product.sarc"
+pub fn resource_name(name: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if ext.starts_with('s') && ext.len() > 1 && ext != "sarc" => format!("{}.{}", stem, &ext[1..]),
+        _ => name.to_string()
+    }
+}
+
+// The size the game reserves for a resource: its uncompressed size rounded up
+// to 32 bytes, plus the loader's overhead and the size of the resource class.
+// The overheads are the ones community RSTB tools use for archives, other
+// files get the base resource class.
+pub fn resource_size(name: &str, data: &[u8], endianness: Endianness) -> Result<u32> {
+    let len = if yaz0::is_compressed(data) { yaz0::decompress(data)?.len() } else { data.len() };
+    let wiiu = endianness == Endianness::Big;
+    let archive = matches!(resource_name(name).rsplit('.').next(), Some("sarc") | Some("pack"));
+    let overhead = match (wiiu, archive) {
+        (true, true) => 0xE4 + 0x68,
+        (true, false) => 0xE4 + 0x20,
+        (false, true) => 0x168 + 0xB0,
+        (false, false) => 0x168 + 0x38
+    };
+    Ok(((len + 31) & !31) as u32 + overhead)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two CRC entries and a name kept by name, in the given byte order
+    fn table(endianness: Endianness) -> Vec<u8> {
+        let u32_bytes = |n: u32| match endianness {
+            Endianness::Little => n.to_le_bytes(),
+            Endianness::Big => n.to_be_bytes()
+        };
+        let mut bytes = b"RSTB".to_vec();
+        bytes.extend(&u32_bytes(2));
+        bytes.extend(&u32_bytes(1));
+        for &(crc, size) in &[(crc32("Message/Msg_USen.product.sarc"), 0x1000), (crc32("Pack/Bootup.pack"), 0x2000)] {
+            bytes.extend(&u32_bytes(crc));
+            bytes.extend(&u32_bytes(size));
+        }
+        let mut name = b"Actor/Colliding.bxml".to_vec();
+        name.resize(NAME_SIZE, 0);
+        bytes.extend(&name);
+        bytes.extend(&u32_bytes(0x300));
+        bytes
+    }
+
+    #[test]
+    fn computes_crc32() {
+        assert_eq!(crc32(""), 0);
+        assert_eq!(crc32("123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn reads_both_byte_orders() {
+        for &endianness in &[Endianness::Big, Endianness::Little] {
+            let bytes = table(endianness);
+            let rstb = Rstb::from_bytes(&bytes).unwrap();
+            assert_eq!(rstb.endianness, endianness);
+            assert_eq!(rstb.crc_entries.len(), 2);
+            assert_eq!(rstb.get("Message/Msg_USen.product.sarc"), Some(0x1000));
+            assert_eq!(rstb.get("Pack/Bootup.pack"), Some(0x2000));
+            assert_eq!(rstb.get("Actor/Colliding.bxml"), Some(0x300));
+            assert_eq!(rstb.get("Missing"), None);
+            assert_eq!(rstb.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn reads_yaz0_compressed_tables() {
+        let rstb = Rstb::from_bytes(&table(Endianness::Little)).unwrap();
+        let mut compressed = Vec::new();
+        rstb.write_compressed_to(&mut compressed, 6).unwrap();
+        assert!(yaz0::is_compressed(&compressed));
+        assert_eq!(Rstb::from_bytes(&compressed).unwrap().to_bytes(), rstb.to_bytes());
+    }
+
+    #[test]
+    fn rejects_bad_tables() {
+        let bytes = table(Endianness::Big);
+        assert!(Rstb::from_bytes(b"RSTB").is_err());
+        assert!(Rstb::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Rstb::from_bytes(b"BTSR\0\0\0\0\0\0\0\0").is_err());
+    }
+
+    #[test]
+    fn grows_and_sets_crc_entries() {
+        let mut rstb = Rstb::from_bytes(&table(Endianness::Big)).unwrap();
+        let name = "Message/Msg_USen.product.sarc";
+        assert_eq!(rstb.grow(name, 0x800), None);
+        assert_eq!(rstb.get(name), Some(0x1000));
+        assert_eq!(rstb.grow(name, 0x1800), Some(0x1000));
+        assert_eq!(rstb.get(name), Some(0x1800));
+        rstb.set(name, 0x400);
+        assert_eq!(rstb.crc_entries[&crc32(name)], 0x400);
+        assert!(rstb.name_entries.get(name).is_none());
+
+        // missing entries aren't added by grow, only by set
+        assert_eq!(rstb.grow("Message/Msg_EUfr.product.sarc", 0x1000), None);
+        assert_eq!(rstb.crc_entries.len(), 2);
+        rstb.set("Message/Msg_EUfr.product.sarc", 0x1000);
+        assert_eq!(rstb.crc_entries.len(), 3);
+    }
+
+    #[test]
+    fn grows_and_sets_named_entries() {
+        let mut rstb = Rstb::from_bytes(&table(Endianness::Little)).unwrap();
+        let name = "Actor/Colliding.bxml";
+        // a CRC entry for the same hash belongs to the name it collides with
+        rstb.crc_entries.insert(crc32(name), 0x5000);
+        assert_eq!(rstb.get(name), Some(0x300));
+        assert_eq!(rstb.grow(name, 0x400), Some(0x300));
+        assert_eq!(rstb.name_entries[name], 0x400);
+        rstb.set(name, 0x200);
+        assert_eq!(rstb.name_entries[name], 0x200);
+        assert_eq!(rstb.crc_entries[&crc32(name)], 0x5000);
+    }
+
+    #[test]
+    fn names_resources_uncompressed() {
+        assert_eq!(resource_name("Message/Msg_EUfr.product.ssarc"), "Message/Msg_EUfr.product.sarc");
+        assert_eq!(resource_name("Pack/Bootup.spack"), "Pack/Bootup.pack");
+        assert_eq!(resource_name("Message/Msg_EUfr.product.sarc"), "Message/Msg_EUfr.product.sarc");
+        assert_eq!(resource_name("Actor/Colliding.bxml"), "Actor/Colliding.bxml");
+    }
+}
@@ -0,0 +1,302 @@
+use crate::error::{Error, Result};
+use crate::yaz0;
+use byteordered::{ByteOrdered, Endianness};
+use std::io::{Read, Write};
+
+const HEADER_SIZE: usize = 0x14;
+const SFAT_HEADER_SIZE: usize = 0xC;
+const SFAT_NODE_SIZE: usize = 0x10;
+const SFNT_HEADER_SIZE: usize = 0x8;
+const HASH_KEY: u32 = 0x65;
+// the largest data alignment guessed from an archive's layout
+const MAX_ALIGNMENT: u32 = 0x2000;
+
+#[derive(Debug, Clone)]
+pub struct SarcFile {
+    pub name: String,
+    pub data: Vec<u8>
+}
+
+// A SARC archive, as used for BotW's Bootup packs and message archives. Files
+// are kept in archive order; writing sorts them by name hash as the format
+// requires and starts each one on a multiple of `alignment`.
+#[derive(Debug, Clone)]
+pub struct Sarc {
+    pub endianness: Endianness,
+    pub alignment: u32,
+    pub files: Vec<SarcFile>
+}
+
+// Names are hashed as signed chars
+pub fn name_hash(name: &str, key: u32) -> u32 {
+    name.bytes().fold(0, |hash, b| hash.wrapping_mul(key).wrapping_add(b as i8 as u32))
+}
+
+fn bad(reason: String) -> Error {
+    Error::Sarc(reason)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    endianness: Endianness
+}
+
+impl<'a> Reader<'a> {
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        self.bytes.get(offset..offset + len)
+            .ok_or_else(|| bad(format!("{} bytes at 0x{:X} are past the end", len, offset)))
+    }
+    fn u16(&self, offset: usize) -> Result<u16> {
+        let b = self.slice(offset, 2)?;
+        Ok(match self.endianness {
+            Endianness::Little => u16::from_le_bytes([b[0], b[1]]),
+            Endianness::Big => u16::from_be_bytes([b[0], b[1]])
+        })
+    }
+    fn u32(&self, offset: usize) -> Result<u32> {
+        let b = self.slice(offset, 4)?;
+        Ok(match self.endianness {
+            Endianness::Little => u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            Endianness::Big => u32::from_be_bytes([b[0], b[1], b[2], b[3]])
+        })
+    }
+    fn magic(&self, offset: usize, magic: &[u8]) -> Result<()> {
+        if self.slice(offset, magic.len())? != magic {
+            return Err(bad(format!("expected {} at 0x{:X}", String::from_utf8_lossy(magic), offset)));
+        }
+        Ok(())
+    }
+}
+
+fn align(n: usize, alignment: usize) -> usize {
+    n.div_ceil(alignment) * alignment
+}
+
+impl Sarc {
+    pub fn new(endianness: Endianness) -> Self {
+        Self{ endianness, alignment: 4, files: Vec::new() }
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+
+    // Yaz0-compressed archives (.ssarc, .pack) are decompressed first
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if yaz0::is_compressed(bytes) {
+            return Self::from_bytes(&yaz0::decompress(bytes)?);
+        }
+        let endianness = match bytes.get(6..8) {
+            Some([0xFE, 0xFF]) => Endianness::Big,
+            Some([0xFF, 0xFE]) => Endianness::Little,
+            _ => return Err(bad("missing byte order mark".to_string()))
+        };
+        let rdr = Reader{ bytes, endianness };
+        rdr.magic(0, b"SARC")?;
+        let sfat = rdr.u16(4)? as usize;
+        let data_offset = rdr.u32(0xC)? as usize;
+        rdr.magic(sfat, b"SFAT")?;
+        let count = rdr.u16(sfat + 6)? as usize;
+        let nodes = sfat + rdr.u16(sfat + 4)? as usize;
+        let sfnt = nodes + count * SFAT_NODE_SIZE;
+        rdr.magic(sfnt, b"SFNT")?;
+        let names = sfnt + rdr.u16(sfnt + 4)? as usize;
+
+        let mut files = Vec::new();
+        let mut starts = vec![data_offset];
+        for i in 0..count {
+            let node = nodes + i * SFAT_NODE_SIZE;
+            let attributes = rdr.u32(node + 4)?;
+            if attributes >> 24 == 0 {
+                return Err(bad(format!("file {} has no name", i)));
+            }
+            let name_start = names + (attributes & 0xFFFFFF) as usize * 4;
+            let name_len = bytes.get(name_start..).and_then(|b| b.iter().position(|&c| c == 0))
+                .ok_or_else(|| bad(format!("file {} has a bad name offset", i)))?;
+            let name = String::from_utf8_lossy(&bytes[name_start..name_start + name_len]).to_string();
+            let (start, end) = (rdr.u32(node + 8)? as usize, rdr.u32(node + 12)? as usize);
+            if end < start {
+                return Err(bad(format!("{} ends before it starts", name)));
+            }
+            let data = rdr.slice(data_offset + start, end - start)
+                .map_err(|e| bad(format!("{}: {}", name, e)))?
+                .to_vec();
+            starts.push(data_offset + start);
+            files.push(SarcFile{ name, data });
+        }
+        let alignment = (2..=MAX_ALIGNMENT.trailing_zeros())
+            .map(|shift| 1 << shift)
+            .take_while(|&a: &u32| starts.iter().all(|&s| s % a as usize == 0))
+            .last()
+            .unwrap_or(4);
+        Ok(Self{ endianness, alignment, files })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SarcFile> {
+        self.files.iter().find(|f| f.name == name)
+    }
+
+    // Replaces the file with this name, or adds it
+    pub fn insert(&mut self, name: &str, data: Vec<u8>) {
+        match self.files.iter_mut().find(|f| f.name == name) {
+            Some(file) => file.data = data,
+            None => self.files.push(SarcFile{ name: name.to_string(), data })
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let alignment = self.alignment.max(1) as usize;
+        let mut files: Vec<(u32, &SarcFile)> = self.files.iter().map(|f| (name_hash(&f.name, HASH_KEY), f)).collect();
+        files.sort_by_key(|(hash, _)| *hash);
+
+        let mut names = Vec::new();
+        let mut data = Vec::new();
+        let mut nodes = Vec::new();
+        for (hash, file) in &files {
+            let name_offset = names.len();
+            names.extend(file.name.as_bytes());
+            names.push(0);
+            names.resize(align(names.len(), 4), 0);
+            data.resize(align(data.len(), alignment), 0);
+            let start = data.len();
+            data.extend(&file.data);
+            nodes.push((*hash, 0x0100_0000 | (name_offset / 4) as u32, start as u32, data.len() as u32));
+        }
+        let sfnt = HEADER_SIZE + SFAT_HEADER_SIZE + nodes.len() * SFAT_NODE_SIZE;
+        let data_offset = align(sfnt + SFNT_HEADER_SIZE + names.len(), alignment);
+
+        let mut result = Vec::with_capacity(data_offset + data.len());
+        let mut writer = ByteOrdered::runtime(&mut result, self.endianness);
+        writer.write_all(b"SARC").unwrap();
+        writer.write_u16(HEADER_SIZE as u16).unwrap();
+        writer.write_u16(0xFEFF).unwrap();
+        writer.write_u32((data_offset + data.len()) as u32).unwrap();
+        writer.write_u32(data_offset as u32).unwrap();
+        writer.write_u16(0x0100).unwrap();
+        writer.write_u16(0).unwrap();
+        writer.write_all(b"SFAT").unwrap();
+        writer.write_u16(SFAT_HEADER_SIZE as u16).unwrap();
+        writer.write_u16(nodes.len() as u16).unwrap();
+        writer.write_u32(HASH_KEY).unwrap();
+        for (hash, attributes, start, end) in nodes {
+            writer.write_u32(hash).unwrap();
+            writer.write_u32(attributes).unwrap();
+            writer.write_u32(start).unwrap();
+            writer.write_u32(end).unwrap();
+        }
+        writer.write_all(b"SFNT").unwrap();
+        writer.write_u16(SFNT_HEADER_SIZE as u16).unwrap();
+        writer.write_u16(0).unwrap();
+        writer.write_all(&names).unwrap();
+        result.resize(data_offset, 0);
+        result.extend(data);
+        result
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    // Writes the archive Yaz0-compressed at the given level
+    pub fn write_compressed_to<W: Write>(&self, mut writer: W, level: u32) -> Result<()> {
+        writer.write_all(&yaz0::compress(&self.to_bytes(), level))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(endianness: Endianness, alignment: u32) -> Sarc {
+        let mut sarc = Sarc::new(endianness);
+        sarc.alignment = alignment;
+        sarc.insert("EventFlowMsg/Npc_Kakariko001.msbt", b"MsgStdBn one".to_vec());
+        sarc.insert("ActorType/ArmorHead.msbt", b"MsgStdBn two, a little longer".to_vec());
+        sarc.insert("StaticMsg/Shop.msbt", vec![0xAB; 3]);
+        sarc
+    }
+
+    fn node_hashes(bytes: &[u8], endianness: Endianness) -> Vec<u32> {
+        let rdr = Reader{ bytes, endianness };
+        let count = rdr.u16(HEADER_SIZE + 6).unwrap() as usize;
+        (0..count).map(|i| rdr.u32(HEADER_SIZE + SFAT_HEADER_SIZE + i * SFAT_NODE_SIZE).unwrap()).collect()
+    }
+
+    #[test]
+    fn round_trips_in_both_byte_orders() {
+        for &endianness in &[Endianness::Big, Endianness::Little] {
+            let sarc = sample(endianness, 4);
+            let bytes = sarc.to_bytes();
+            let read = Sarc::from_bytes(&bytes).unwrap();
+            assert_eq!(read.endianness, endianness);
+            assert_eq!(read.files.len(), 3);
+            for file in &sarc.files {
+                assert_eq!(read.get(&file.name).unwrap().data, file.data);
+            }
+            assert_eq!(read.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn sorts_nodes_by_name_hash() {
+        let bytes = sample(Endianness::Big, 4).to_bytes();
+        let hashes = node_hashes(&bytes, Endianness::Big);
+        let mut sorted = hashes.clone();
+        sorted.sort();
+        assert_eq!(hashes, sorted);
+        let read = Sarc::from_bytes(&bytes).unwrap();
+        let names: Vec<u32> = read.files.iter().map(|f| name_hash(&f.name, HASH_KEY)).collect();
+        assert_eq!(names, hashes);
+    }
+
+    #[test]
+    fn hashes_names_as_signed_chars() {
+        assert_eq!(name_hash("", HASH_KEY), 0);
+        assert_eq!(name_hash("a", HASH_KEY), 0x61);
+        assert_eq!(name_hash("ab", HASH_KEY), 0x61 * 0x65 + 0x62);
+        assert_eq!(name_hash("\u{e9}", HASH_KEY), (0xC3u8 as i8 as u32).wrapping_mul(0x65).wrapping_add(0xA9u8 as i8 as u32));
+    }
+
+    #[test]
+    fn guesses_the_alignment() {
+        for &alignment in &[4, 0x80, 0x2000] {
+            let bytes = sample(Endianness::Little, alignment).to_bytes();
+            let read = Sarc::from_bytes(&bytes).unwrap();
+            // data that happens to land on a larger boundary can raise the guess,
+            // but rewriting with it keeps every file where it was
+            assert!(read.alignment >= alignment && read.alignment % alignment == 0);
+            assert_eq!(read.to_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn reads_yaz0_compressed_archives() {
+        let sarc = sample(Endianness::Big, 4);
+        let mut compressed = Vec::new();
+        sarc.write_compressed_to(&mut compressed, 6).unwrap();
+        let read = Sarc::from_bytes(&compressed).unwrap();
+        assert_eq!(read.to_bytes(), sarc.to_bytes());
+    }
+
+    #[test]
+    fn replaces_files_by_name() {
+        let mut sarc = sample(Endianness::Big, 4);
+        sarc.insert("StaticMsg/Shop.msbt", b"new".to_vec());
+        assert_eq!(sarc.files.len(), 3);
+        assert_eq!(sarc.get("StaticMsg/Shop.msbt").unwrap().data, b"new");
+    }
+
+    #[test]
+    fn rejects_bad_archives() {
+        let bytes = sample(Endianness::Big, 4).to_bytes();
+        assert!(Sarc::from_bytes(&bytes[..0x30]).is_err());
+        assert!(Sarc::from_bytes(b"SARC").is_err());
+        let mut bad_magic = bytes.clone();
+        bad_magic[HEADER_SIZE] = b'X';
+        assert!(Sarc::from_bytes(&bad_magic).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use crate::error::{Error, Result};
+use crate::helper::{self, Param, Value};
+use crate::locale;
+use crate::merge;
+use crate::profile::GameProfile;
+use potty::{Pot, PotMessage};
+use std::collections::HashMap;
+
+// Sub-entries are keyed "<label>|<tag>.<occurrence>.<param>" in msgctxt
+const SEPARATOR: char = '|';
+
+fn string_params(profile: &dyn GameProfile, tag: &str) -> Vec<String> {
+    profile.new_params(tag).into_iter()
+        .filter(|p| matches!(p.value, Value::String(_)))
+        .map(|p| p.name)
+        .collect()
+}
+
+fn param_value<'a>(params: &'a [(String, String)], name: &str) -> &'a str {
+    params.iter().find(|(n, _)| n == name).map_or("", |(_, v)| v.as_str())
+}
+
+fn parse_key<'a>(key: &'a str, tags: &[String]) -> Option<(&'a str, &'a str, usize, &'a str)> {
+    let (label, suffix) = key.rsplit_once(SEPARATOR)?;
+    let mut parts = suffix.splitn(3, '.');
+    let tag = parts.next().filter(|t| tags.iter().any(|tag| tag == t))?;
+    let index = parts.next()?.parse().ok()?;
+    Some((label, tag, index, parts.next()?))
+}
+
+// The string params of each variant tag become entries of their own after the
+// message, leaving the tag with the rest. In bilingual files the matching tag
+// of the source text gives the msgid.
+pub fn split(pot: &mut Pot, profile: &dyn GameProfile) {
+    let tags = profile.variant_tags();
+    let mut messages = Vec::new();
+    for mut message in std::mem::take(&mut pot.messages) {
+        let text = match message.strings.first() {
+            Some(text) if message.id_plural.is_none() && !locale::is_header(&message) => text.clone(),
+            _ => {
+                messages.push(message);
+                continue
+            }
+        };
+        let label = message.context.clone().or_else(|| message.id.clone()).unwrap_or_default();
+        let source = message.context.as_ref().and(message.id.clone());
+        let mut text = text;
+        let mut entries = Vec::new();
+        for tag in &tags {
+            let names = string_params(profile, tag);
+            let source_spans = source.as_deref().map(|s| helper::find_tags(s, tag)).unwrap_or_default();
+            let spans = helper::find_tags(&text, tag);
+            for (i, span) in spans.iter().enumerate() {
+                for name in &names {
+                    let key = format!("{}{}{}.{}.{}", label, SEPARATOR, tag, i, name);
+                    let mut entry = PotMessage::new();
+                    // msgid can't be empty, so the key stands in for missing source text
+                    entry.id = Some(source_spans.get(i)
+                        .map(|s| param_value(&s.params, name))
+                        .filter(|v| !v.is_empty())
+                        .map_or_else(|| key.clone(), helper::escape_text));
+                    entry.context = Some(key);
+                    entry.strings.push(helper::escape_text(param_value(&span.params, name)));
+                    entries.push(entry);
+                }
+            }
+            for span in spans.iter().rev() {
+                let kept: Vec<Param> = span.params.iter()
+                    .filter(|(n, _)| !names.contains(n))
+                    .map(|(n, v)| Param::new(n, Value::String(v.clone())))
+                    .collect();
+                text.replace_range(span.start..span.end, &helper::format_tag(tag, &kept));
+            }
+        }
+        let changed = text != message.strings[0];
+        message.strings[0] = text;
+        // the game text recorded on extraction still has the params taken out
+        if changed && message.context.is_none() {
+            merge::record_source(&mut message);
+        }
+        messages.push(message);
+        messages.append(&mut entries);
+    }
+    pot.messages = messages;
+}
+
+// Puts the values of sub-entries made by `split` back into their tags. Expects
+// resolved messages, with the key in msgid and the value in msgstr.
+pub fn join(messages: Vec<PotMessage>, profile: &dyn GameProfile) -> Result<Vec<PotMessage>> {
+    let tags = profile.variant_tags();
+    let mut values: HashMap<String, Vec<PotMessage>> = HashMap::new();
+    let mut joined = Vec::new();
+    for message in messages {
+        match message.id.as_deref().and_then(|id| parse_key(id, &tags)) {
+            Some((label, ..)) => values.entry(label.to_string()).or_default().push(message),
+            None => joined.push(message)
+        }
+    }
+
+    for message in joined.iter_mut() {
+        let entries = match message.id.as_ref().and_then(|id| values.remove(id)) {
+            Some(entries) => entries,
+            _ => continue
+        };
+        let mut text = message.strings.first().cloned().unwrap_or_default();
+        for tag in &tags {
+            let spans = helper::find_tags(&text, tag);
+            let mut added = vec![Vec::new(); spans.len()];
+            for entry in &entries {
+                let key = entry.id.as_deref().unwrap_or_default();
+                let (_, entry_tag, index, param) = parse_key(key, &tags).unwrap();
+                if entry_tag != tag {
+                    continue;
+                }
+                let value = helper::unescape_text(entry.strings.first().map_or("", String::as_str));
+                added.get_mut(index)
+                    .ok_or_else(|| Error::Po(format!("no [{}] number {} in the message", tag, index + 1)).in_message(key))?
+                    .push((param.to_string(), value));
+            }
+            for (span, added) in spans.iter().zip(added).rev() {
+                let order: Vec<String> = profile.new_params(tag).into_iter().map(|p| p.name).collect();
+                let mut params: Vec<(String, String)> = span.params.iter().cloned().chain(added).collect();
+                params.sort_by_key(|(n, _)| order.iter().position(|o| o == n).unwrap_or(order.len()));
+                let params: Vec<Param> = params.into_iter()
+                    .map(|(n, v)| Param::new(&n, Value::String(v)))
+                    .collect();
+                text.replace_range(span.start..span.end, &helper::format_tag(tag, &params));
+            }
+        }
+        message.strings = vec![text];
+    }
+
+    match values.into_iter().next() {
+        Some((label, entries)) => Err(Error::Po(format!("no message \"{}\"", label))
+            .in_message(entries[0].id.as_deref().unwrap_or_default())),
+        None => Ok(joined)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games;
+
+    fn message(context: Option<&str>, id: &str, text: &str) -> PotMessage {
+        let mut message = PotMessage::new();
+        message.context = context.map(str::to_string);
+        message.id = Some(id.to_string());
+        message.strings.push(text.to_string());
+        message
+    }
+
+    // what msbt_from_po hands to join: the key in msgid and the value in msgstr
+    fn resolved(messages: &[PotMessage]) -> Vec<PotMessage> {
+        messages.iter().map(|m| {
+            let mut m = m.clone();
+            if let Some(key) = m.context.take() {
+                m.id = Some(key);
+            }
+            m
+        }).collect()
+    }
+
+    const TEXT: &str = r#"[Gender masculine="He" feminine="She"] found [Particle consonant="i" vowel="ga"] [[x] and [Gender masculine="his" feminine="her"]"#;
+
+    #[test]
+    fn splits_and_joins_monolingual() {
+        let profile = games::botw().unwrap();
+        let mut pot = Pot::new();
+        pot.messages.push(message(None, "Label", TEXT));
+        split(&mut pot, &profile);
+        let texts: Vec<(&str, &str)> = pot.messages.iter()
+            .map(|m| (m.context.as_deref().unwrap_or_default(), m.strings[0].as_str()))
+            .collect();
+        assert_eq!(texts, vec![
+            ("", "[Gender] found [Particle] [[x] and [Gender]"),
+            ("Label|Gender.0.masculine", "He"),
+            ("Label|Gender.0.feminine", "She"),
+            ("Label|Gender.0.unk", ""),
+            ("Label|Gender.1.masculine", "his"),
+            ("Label|Gender.1.feminine", "her"),
+            ("Label|Gender.1.unk", ""),
+            ("Label|Particle.0.consonant", "i"),
+            ("Label|Particle.0.vowel", "ga")
+        ]);
+        // with no source text the key stands in for msgid
+        assert_eq!(pot.messages[1].id.as_deref(), Some("Label|Gender.0.masculine"));
+        assert_eq!(pot.messages[0].extracted_comments, vec!["source: [Gender] found [Particle] [[x] and [Gender]"]);
+
+        let joined = join(resolved(&pot.messages), &profile).unwrap();
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0].strings, vec![TEXT]);
+    }
+
+    #[test]
+    fn splits_bilingual_source() {
+        let profile = games::botw().unwrap();
+        let mut pot = Pot::new();
+        pot.messages.push(message(Some("Label"), r#"[Particle consonant="a[b" vowel=""]"#,
+                                  r#"[Particle consonant="i" vowel="ga"]"#));
+        split(&mut pot, &profile);
+        assert_eq!(pot.messages[0].strings, vec!["[Particle]"]);
+        assert!(pot.messages[0].extracted_comments.is_empty());
+        // source values are escaped like any msgid, and empty ones fall back to the key
+        assert_eq!(pot.messages[1].id.as_deref(), Some("a[[b"));
+        assert_eq!(pot.messages[2].id.as_deref(), Some("Label|Particle.0.vowel"));
+
+        let mut messages = resolved(&pot.messages);
+        messages[1].strings = vec!["[[o]".to_string()];
+        let joined = join(messages, &profile).unwrap();
+        assert_eq!(joined[0].strings, vec![r#"[Particle consonant="[o]" vowel="ga"]"#]);
+    }
+
+    #[test]
+    fn rejects_entries_without_their_tag() {
+        let profile = games::botw().unwrap();
+        let messages = vec![message(None, "Label", "[Particle]"), message(None, "Label|Particle.1.vowel", "ga")];
+        assert!(join(messages, &profile).is_err());
+        let messages = vec![message(None, "Label", "[Particle]"), message(None, "Other|Particle.0.vowel", "ga")];
+        assert!(join(messages, &profile).is_err());
+        // keys with tags that don't have variants are ordinary labels
+        let messages = vec![message(None, "Label|Color.0.name", "red")];
+        assert_eq!(join(messages, &profile).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,185 @@
+use msbt::Msbt;
+use msbt::section::txt2::Token;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Section {
+    Lbl1,
+    Txt2,
+    Atr1,
+    Ato1,
+    Tsy1,
+    Nli1,
+    File
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Lbl1 => "LBL1",
+            Self::Txt2 => "TXT2",
+            Self::Atr1 => "ATR1",
+            Self::Ato1 => "ATO1",
+            Self::Tsy1 => "TSY1",
+            Self::Nli1 => "NLI1",
+            Self::File => "file"
+        })
+    }
+}
+
+// `offset` is relative to the differing item: the label name for LBL1, the token
+// for TXT2, the section body for ATR1/ATO1/TSY1 and the id table for NLI1.
+#[derive(Debug, Clone)]
+pub struct Mismatch {
+    pub section: Section,
+    pub label: Option<String>,
+    pub token: Option<usize>,
+    pub offset: usize,
+    pub file_offset: Option<usize>
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} differs", self.section)?;
+        if let Some(ref label) = self.label {
+            write!(f, " at label \"{}\"", label)?;
+        }
+        if let Some(token) = self.token {
+            write!(f, " token {}", token)?;
+        }
+        write!(f, " byte {}", self.offset)?;
+        if let Some(file_offset) = self.file_offset {
+            write!(f, " (file offset 0x{:X})", file_offset)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub original_len: usize,
+    pub rebuilt_len: usize,
+    pub mismatch: Option<Mismatch>
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.mismatch.is_none()
+    }
+}
+
+fn first_diff(a: &[u8], b: &[u8]) -> Option<usize> {
+    a.iter().zip(b.iter())
+        .position(|(x, y)| x != y)
+        .or_else(|| if a.len() != b.len() { Some(a.len().min(b.len())) } else { None })
+}
+
+fn token_bytes(token: &Token) -> &[u8] {
+    match token {
+        Token::Text(b) => b,
+        Token::TagStart(_group, _tag, params) => params,
+        _ => &[]
+    }
+}
+
+fn mismatch(section: Section, label: Option<&str>, token: Option<usize>, offset: usize) -> Option<Mismatch> {
+    Some(Mismatch {
+        section, token, offset,
+        label: label.map(|l| l.to_string()),
+        file_offset: None
+    })
+}
+
+fn compare_raw(section: Section, a: Option<&[u8]>, b: Option<&[u8]>) -> Option<Mismatch> {
+    match (a, b) {
+        (None, None) => None,
+        (Some(a), Some(b)) => first_diff(a, b).and_then(|offset| mismatch(section, None, None, offset)),
+        _ => mismatch(section, None, None, 0)
+    }
+}
+
+fn compare_sections(original: &Msbt, rebuilt: &Msbt) -> Option<Mismatch> {
+    let (lbl_a, lbl_b) = (original.lbl1().unwrap(), rebuilt.lbl1().unwrap());
+    let (labels_a, labels_b) = (lbl_a.labels(), lbl_b.labels());
+    if lbl_a.groups().len() != lbl_b.groups().len() {
+        return mismatch(Section::Lbl1, None, None, 0);
+    }
+    for (i, label) in labels_a.iter().enumerate() {
+        let other = match labels_b.get(i) {
+            Some(other) => other,
+            None => return mismatch(Section::Lbl1, Some(label.name()), None, 0)
+        };
+        if let Some(offset) = first_diff(label.name().as_bytes(), other.name().as_bytes()) {
+            return mismatch(Section::Lbl1, Some(label.name()), None, offset);
+        }
+    }
+    if labels_b.len() > labels_a.len() {
+        return mismatch(Section::Lbl1, Some(labels_b[labels_a.len()].name()), None, 0);
+    }
+
+    let (values_a, values_b) = (original.txt2().unwrap().values(), rebuilt.txt2().unwrap().values());
+    for (i, label) in labels_a.iter().enumerate() {
+        let empty = Vec::new();
+        let tokens_a = values_a.get(i).unwrap_or(&empty);
+        let tokens_b = values_b.get(i).unwrap_or(&empty);
+        for j in 0..tokens_a.len().max(tokens_b.len()) {
+            match (tokens_a.get(j), tokens_b.get(j)) {
+                (Some(a), Some(b)) if a == b => continue,
+                (Some(a), Some(b)) => {
+                    let offset = if std::mem::discriminant(a) == std::mem::discriminant(b) {
+                        first_diff(token_bytes(a), token_bytes(b)).unwrap_or(0)
+                    } else {
+                        0
+                    };
+                    return mismatch(Section::Txt2, Some(label.name()), Some(j), offset);
+                },
+                _ => return mismatch(Section::Txt2, Some(label.name()), Some(j), 0)
+            }
+        }
+    }
+
+    compare_raw(Section::Atr1,
+        original.atr1().map(|s| s.unknown_bytes()), rebuilt.atr1().map(|s| s.unknown_bytes()))
+    .or_else(|| compare_raw(Section::Ato1,
+        original.ato1().map(|s| s.unknown_bytes()), rebuilt.ato1().map(|s| s.unknown_bytes())))
+    .or_else(|| compare_raw(Section::Tsy1,
+        original.tsy1().map(|s| s.unknown_bytes()), rebuilt.tsy1().map(|s| s.unknown_bytes())))
+    .or_else(|| match (original.nli1(), rebuilt.nli1()) {
+        (None, None) => None,
+        (Some(a), Some(b)) => {
+            if a.id_count() != b.id_count() {
+                return mismatch(Section::Nli1, None, None, 0);
+            }
+            a.global_ids().iter().zip(b.global_ids().iter())
+                .position(|(x, y)| x != y)
+                .or_else(|| if a.global_ids().len() != b.global_ids().len() {
+                    Some(a.global_ids().len().min(b.global_ids().len()))
+                } else {
+                    None
+                })
+                .and_then(|entry| mismatch(Section::Nli1, None, None, entry))
+        },
+        _ => mismatch(Section::Nli1, None, None, 0)
+    })
+}
+
+pub fn compare(original: &Msbt, original_bytes: &[u8], rebuilt: &Msbt, rebuilt_bytes: &[u8]) -> Report {
+    let file_offset = first_diff(original_bytes, rebuilt_bytes);
+    let mismatch = file_offset.map(|file_offset| {
+        let mut mismatch = compare_sections(original, rebuilt).unwrap_or(Mismatch {
+            section: Section::File,
+            label: None,
+            token: None,
+            offset: file_offset,
+            file_offset: None
+        });
+        mismatch.file_offset = Some(file_offset);
+        mismatch
+    });
+
+    Report {
+        original_len: original_bytes.len(),
+        rebuilt_len: rebuilt_bytes.len(),
+        mismatch
+    }
+}
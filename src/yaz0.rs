@@ -0,0 +1,255 @@
+use crate::error::{Error, Result};
+
+const HEADER_SIZE: usize = 0x10;
+const WINDOW: usize = 0x1000;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 0xFF + 0x12;
+const HASH_BITS: usize = 15;
+const NONE: usize = usize::MAX;
+
+pub const DEFAULT_LEVEL: u32 = 6;
+pub const MAX_LEVEL: u32 = 9;
+
+fn bad(reason: String) -> Error {
+    Error::Yaz0(reason)
+}
+
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(b"Yaz0")
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if !is_compressed(data) || data.len() < HEADER_SIZE {
+        return Err(bad("missing Yaz0 header".to_string()));
+    }
+    let size = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+    let mut out = Vec::with_capacity(size);
+    let mut pos = HEADER_SIZE;
+    let next = |pos: &mut usize| -> Result<u8> {
+        let byte = *data.get(*pos).ok_or_else(|| bad(format!("data ends at 0x{:X}", pos)))?;
+        *pos += 1;
+        Ok(byte)
+    };
+    while out.len() < size {
+        let group = next(&mut pos)?;
+        for bit in (0..8).rev() {
+            if out.len() >= size {
+                break;
+            }
+            if group >> bit & 1 == 1 {
+                out.push(next(&mut pos)?);
+                continue;
+            }
+            let (b1, b2) = (next(&mut pos)? as usize, next(&mut pos)? as usize);
+            let distance = ((b1 & 0xF) << 8 | b2) + 1;
+            let len = match b1 >> 4 {
+                0 => next(&mut pos)? as usize + 0x12,
+                n => n + 2
+            };
+            if distance > out.len() {
+                return Err(bad(format!("copy from {} bytes back at output 0x{:X}", distance, out.len())));
+            }
+            let start = out.len() - distance;
+            // copies can overlap what they write
+            for i in 0..len.min(size - out.len()) {
+                out.push(out[start + i]);
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Decompresses Yaz0 data and passes anything else through
+pub fn decompress_if_needed(data: Vec<u8>) -> Result<Vec<u8>> {
+    if is_compressed(&data) {
+        decompress(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize) & ((1 << HASH_BITS) - 1)
+}
+
+// Earlier positions with the same three-byte hash, newest first
+struct Chains<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>
+}
+
+impl<'a> Chains<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self{ data, head: vec![NONE; 1 << HASH_BITS], prev: vec![NONE; data.len()] }
+    }
+    fn insert(&mut self, i: usize) {
+        if i + MIN_MATCH <= self.data.len() {
+            let h = hash(self.data, i);
+            self.prev[i] = self.head[h];
+            self.head[h] = i;
+        }
+    }
+}
+
+// The data alignment stored in the header, which Switch files set and Wii U
+// files leave at 0
+pub fn alignment(data: &[u8]) -> u32 {
+    match data.get(8..12) {
+        Some(b) if is_compressed(data) => u32::from_be_bytes([b[0], b[1], b[2], b[3]]),
+        _ => 0
+    }
+}
+
+pub fn compress(data: &[u8], level: u32) -> Vec<u8> {
+    compress_aligned(data, level, 0)
+}
+
+// Level 0 only stores literals; each level above doubles how many earlier
+// matches are tried, from 4 at level 1 to 1024 at level 9
+pub fn compress_aligned(data: &[u8], level: u32, alignment: u32) -> Vec<u8> {
+    let tries = match level.min(MAX_LEVEL) {
+        0 => 0,
+        level => 1 << (level + 1)
+    };
+    let mut out = Vec::with_capacity(HEADER_SIZE + data.len() + data.len() / 8 + 1);
+    out.extend(b"Yaz0");
+    out.extend(&(data.len() as u32).to_be_bytes());
+    out.extend(&alignment.to_be_bytes());
+    out.extend(&[0; 4]);
+
+    let mut chains = Chains::new(data);
+    let mut i = 0;
+    let mut group_pos = 0;
+    let mut group_bit = 0;
+    while i < data.len() {
+        if group_bit == 0 {
+            group_pos = out.len();
+            out.push(0);
+            group_bit = 8;
+        }
+        group_bit -= 1;
+
+        let (mut best_len, mut best_pos) = (0, 0);
+        if tries > 0 && i + MIN_MATCH <= data.len() {
+            let max_len = MAX_MATCH.min(data.len() - i);
+            let mut candidate = chains.head[hash(data, i)];
+            let mut tried = 0;
+            while candidate != NONE && i - candidate <= WINDOW && tried < tries {
+                let len = data[candidate..].iter().zip(&data[i..i + max_len]).take_while(|(a, b)| a == b).count();
+                if len > best_len {
+                    best_len = len;
+                    best_pos = candidate;
+                    if len == max_len {
+                        break;
+                    }
+                }
+                candidate = chains.prev[candidate];
+                tried += 1;
+            }
+        }
+
+        if best_len >= MIN_MATCH {
+            let distance = i - best_pos - 1;
+            if best_len < 0x12 {
+                out.push(((best_len - 2) << 4 | distance >> 8) as u8);
+                out.push(distance as u8);
+            } else {
+                out.push((distance >> 8) as u8);
+                out.push(distance as u8);
+                out.push((best_len - 0x12) as u8);
+            }
+            for j in i..i + best_len {
+                chains.insert(j);
+            }
+            i += best_len;
+        } else {
+            out[group_pos] |= 1 << group_bit;
+            out.push(data[i]);
+            chains.insert(i);
+            i += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..2000u32 {
+            data.extend(format!("Msg_{:03} ", i % 97).bytes());
+        }
+        // a pseudo-random stretch with few matches
+        data.extend((0..3000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8));
+        data
+    }
+
+    #[test]
+    fn round_trips_at_each_level() {
+        let data = sample();
+        for &level in &[0, 1, 9] {
+            let compressed = compress(&data, level);
+            assert!(is_compressed(&compressed));
+            assert_eq!(decompress(&compressed).unwrap(), data, "level {}", level);
+        }
+        assert!(compress(&data, 9).len() < compress(&data, 1).len());
+        assert!(compress(&data, 1).len() < compress(&data, 0).len());
+    }
+
+    #[test]
+    fn round_trips_short_inputs() {
+        for data in &[vec![], vec![1], vec![1, 2], vec![1, 1, 1], vec![1, 1, 1, 1]] {
+            assert_eq!(&decompress(&compress(data, 9)).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn overlapping_and_long_matches() {
+        // one byte repeated copies from a distance of 1, and "ab" repeated from 2,
+        // both in matches longer than 0x12 that need the three-byte form
+        let mut data = vec![7u8; 1000];
+        data.extend(b"ab".iter().cycle().take(600));
+        let compressed = compress(&data, 1);
+        // 0x111 bytes from 1 back
+        assert!(compressed.windows(3).any(|w| w == [0x00, 0x00, 0xFF]));
+        assert!(compressed.len() < 50);
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn decodes_both_match_forms() {
+        let mut stream = b"Yaz0".to_vec();
+        stream.extend(&27u32.to_be_bytes());
+        stream.extend(&[0; 8]);
+        // literal "a", 4 bytes from 1 back, literal "b", 0x12 + 3 bytes from 2 back
+        stream.extend(&[0b1010_0000, b'a', 0x20, 0x00, b'b', 0x00, 0x01, 0x03]);
+        let mut expected = b"aaaaab".to_vec();
+        expected.extend(b"ab".iter().cycle().take(0x15));
+        assert_eq!(decompress(&stream).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let compressed = compress(&sample(), 6);
+        assert!(decompress(&compressed[..compressed.len() / 2]).is_err());
+        assert!(decompress(b"Yaz0").is_err());
+        assert!(decompress(b"SARC and more bytes").is_err());
+        let mut back_too_far = b"Yaz0".to_vec();
+        back_too_far.extend(&4u32.to_be_bytes());
+        back_too_far.extend(&[0; 8]);
+        back_too_far.extend(&[0x00, 0x10, 0x05]);
+        assert!(decompress(&back_too_far).is_err());
+    }
+
+    #[test]
+    fn keeps_alignment_and_passes_plain_data_through() {
+        let compressed = compress_aligned(b"hello hello hello", 6, 0x80);
+        assert_eq!(alignment(&compressed), 0x80);
+        assert_eq!(alignment(b"hello"), 0);
+        assert_eq!(decompress_if_needed(compressed).unwrap(), b"hello hello hello");
+        assert_eq!(decompress_if_needed(b"plain".to_vec()).unwrap(), b"plain");
+    }
+}